@@ -0,0 +1,30 @@
+//! Stopping a running organize operation.
+//!
+//! A [`CancellationToken`] is shared between the thread running the organizer
+//! and whoever may want to stop it, such as a Cancel button or a Ctrl-C handler.
+//! The organizer checks it between files, so the file being transferred is
+//! always finished and the run stays consistent and undoable.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag requesting that an operation stop. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a token that has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests that the operation stop as soon as possible.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if cancellation has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,164 @@
+//! Category rules that group related file extensions into named folders.
+//!
+//! Instead of creating one folder per extension (`jpg/`, `jpeg/`, `png/`), files
+//! whose extension is listed in a [`CategoryRule`] are placed in the folder named
+//! after that category (`Images/`). Extensions that no rule mentions still get a
+//! folder of their own.
+//!
+//! Some file types have an extension made of several parts, such as `tar.gz` or
+//! `user.js`. Those listed in `Config::compound_extensions` are treated as a
+//! single extension rather than just their last part.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A named category and the file extensions that belong to it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CategoryRule {
+    /// Name of the category, also used as the folder name.
+    pub name: String,
+    /// Extensions (without the leading dot) that belong to the category.
+    /// Matching is case-insensitive.
+    pub extensions: Vec<String>,
+}
+
+impl CategoryRule {
+    fn new(name: &str, extensions: &[&str]) -> Self {
+        Self {
+            name: name.to_owned(),
+            extensions: extensions.iter().map(|&extension| extension.to_owned()).collect(),
+        }
+    }
+}
+
+/// The built-in taxonomy used when no categories are configured.
+pub fn default_categories() -> Vec<CategoryRule> {
+    vec![
+        CategoryRule::new(
+            "Images",
+            &[
+                "jpg", "jpeg", "png", "gif", "bmp", "tif", "tiff", "webp", "svg", "heic", "heif", "ico", "raw",
+                "cr2", "cr3", "nef", "arw", "dng", "orf", "rw2",
+            ],
+        ),
+        CategoryRule::new(
+            "Documents",
+            &[
+                "pdf", "doc", "docx", "odt", "rtf", "txt", "md", "tex", "xls", "xlsx", "ods", "csv", "ppt",
+                "pptx", "odp", "epub",
+            ],
+        ),
+        CategoryRule::new("Archives", &["zip", "rar", "7z", "tar", "gz", "tgz", "bz2", "xz", "zst", "iso"]),
+        CategoryRule::new("Audio", &["mp3", "wav", "flac", "aac", "ogg", "oga", "opus", "m4a", "wma", "aiff"]),
+        CategoryRule::new("Video", &["mp4", "mkv", "avi", "mov", "wmv", "webm", "flv", "m4v", "mpg", "mpeg"]),
+        CategoryRule::new(
+            "Code",
+            &[
+                "rs", "py", "js", "ts", "c", "h", "cpp", "hpp", "cs", "java", "kt", "go", "rb", "php", "sh",
+                "ps1", "html", "css", "json", "toml", "yaml", "yml", "xml", "sql",
+            ],
+        ),
+        CategoryRule::new("Executables", &["exe", "msi", "deb", "rpm", "dmg", "pkg", "appimage", "apk", "elf"]),
+        CategoryRule::new("Fonts", &["ttf", "otf", "woff", "woff2"]),
+    ]
+}
+
+/// The built-in list of extensions made of several parts.
+pub fn default_compound_extensions() -> Vec<String> {
+    ["tar.gz", "tar.bz2", "tar.xz", "tar.zst", "tar.lz4", "tar.lzma", "user.js", "user.css", "d.ts"]
+        .map(str::to_owned)
+        .to_vec()
+}
+
+/// Returns the name of the first category listing `extension`, if any. A
+/// compound extension no rule lists falls back to the category of its last
+/// part, so `tar.gz` goes with `gz`.
+pub(crate) fn category_for<'a>(extension: &str, rules: &'a [CategoryRule]) -> Option<&'a str> {
+    let find = |extension: &str| {
+        rules
+            .iter()
+            .find(|rule| rule.extensions.iter().any(|e| e.eq_ignore_ascii_case(extension)))
+            .map(|rule| rule.name.as_str())
+    };
+    find(extension).or_else(|| extension.rsplit_once('.').and_then(|(_, last)| find(last)))
+}
+
+/// Splits `file_name` into its stem and extension, treating any of the
+/// `compound` extensions it ends with as a single extension (the longest, if
+/// several match). Otherwise the extension is what follows the last dot, as
+/// with `Path::extension`, except that a name ending in a dot has none.
+/// Matching is case-insensitive, and the returned parts keep the case of
+/// `file_name`.
+pub(crate) fn split_extension<'a>(file_name: &'a str, compound: &[String]) -> (&'a str, Option<&'a str>) {
+    let bytes = file_name.as_bytes();
+    let longest = compound
+        .iter()
+        .filter(|extension| {
+            let start = bytes.len().saturating_sub(extension.len());
+            // Leave at least one character of stem before the dot.
+            start > 1 && bytes[start - 1] == b'.' && bytes[start..].eq_ignore_ascii_case(extension.as_bytes())
+        })
+        .max_by_key(|extension| extension.len());
+
+    if let Some(extension) = longest {
+        let start = file_name.len() - extension.len();
+        return (&file_name[..start - 1], Some(&file_name[start..]));
+    }
+    match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.is_empty() => (stem, Some(extension)),
+        _ => (file_name, None),
+    }
+}
+
+/// Returns the lowercased extension of `path`, recognising `compound` extensions.
+pub(crate) fn extension_of(path: &Path, compound: &[String]) -> Option<String> {
+    let file_name = path.file_name()?.to_string_lossy();
+    split_extension(&file_name, compound).1.map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_at_the_last_dot() {
+        let compound = default_compound_extensions();
+        assert_eq!(split_extension("photo.jpg", &compound), ("photo", Some("jpg")));
+        assert_eq!(split_extension("archive.backup.zip", &compound), ("archive.backup", Some("zip")));
+        assert_eq!(split_extension("README", &compound), ("README", None));
+    }
+
+    #[test]
+    fn recognises_compound_extensions() {
+        let compound = default_compound_extensions();
+        assert_eq!(split_extension("backup.tar.gz", &compound), ("backup", Some("tar.gz")));
+        assert_eq!(split_extension("Backup.TAR.GZ", &compound), ("Backup", Some("TAR.GZ")));
+        assert_eq!(split_extension("script.user.js", &compound), ("script", Some("user.js")));
+        assert_eq!(split_extension("backup.gz", &compound), ("backup", Some("gz")));
+        assert_eq!(split_extension("backup.tar.gz", &[]), ("backup.tar", Some("gz")));
+    }
+
+    #[test]
+    fn prefers_the_longest_compound_extension() {
+        let compound = ["gz", "tar.gz"].map(str::to_owned);
+        assert_eq!(split_extension("backup.tar.gz", &compound), ("backup", Some("tar.gz")));
+    }
+
+    #[test]
+    fn needs_a_stem_and_an_extension() {
+        let compound = default_compound_extensions();
+        assert_eq!(split_extension(".bashrc", &compound), (".bashrc", None));
+        assert_eq!(split_extension("weird.", &compound), ("weird.", None));
+        assert_eq!(split_extension(".tar.gz", &compound), (".tar", Some("gz")));
+        assert_eq!(split_extension("", &compound), ("", None));
+    }
+
+    #[test]
+    fn compound_extensions_fall_back_to_their_last_part() {
+        let rules = default_categories();
+        assert_eq!(category_for("tar.gz", &rules), category_for("gz", &rules));
+        assert!(category_for("gz", &rules).is_some());
+        assert_eq!(category_for("not-an-extension", &rules), None);
+    }
+}
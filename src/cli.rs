@@ -0,0 +1,478 @@
+//! Command-line interface for running the File Organizer without the GUI.
+//!
+//! Running `fileorg` without arguments (or with the `gui` subcommand) launches the
+//! graphical interface. The other subcommands make the organizer usable from a
+//! terminal, over SSH, or from scheduled jobs.
+
+use chrono::Local;
+use clap::{Args, Parser, Subcommand};
+use std::error::Error;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    apply_plan_with, gui, list_runs, plan_organisation, read_journal, undo_run, watch_directory, AgeSource,
+    CancellationToken, Config, ConflictPolicy, ContentDetection, DateLayout, DateSource, DuplicatePolicy,
+    Plan, ProjectPolicy, RecursiveLayout, SymlinkPolicy, Template, TerminalProgress,
+};
+
+/// A simple File Organizer, usable from the command line or as a GUI.
+#[derive(Parser)]
+#[command(name = "fileorg", version, about)]
+pub struct Cli {
+    /// What to do. Launches the GUI when omitted.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// The available subcommands.
+#[derive(Subcommand)]
+pub enum Command {
+    /// Organize the files in a directory.
+    Organize {
+        #[command(flatten)]
+        organize: OrganizeArgs,
+        /// Print the run report as JSON instead of a human-readable summary.
+        #[arg(long)]
+        json: bool,
+        /// Do not ask for confirmation, however many files would be organized.
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Show what organizing a directory would do, without changing anything.
+    Plan {
+        #[command(flatten)]
+        organize: OrganizeArgs,
+        /// Print the plan as JSON instead of a human-readable listing.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Organize a directory, then keep organizing it as files arrive until
+    /// interrupted with Ctrl-C.
+    Watch(OrganizeArgs),
+    /// Reverse a previous run using its journal.
+    Undo {
+        /// The run to undo. Defaults to the most recent run that has not been undone.
+        run_id: Option<String>,
+        /// List the journaled runs instead of undoing one.
+        #[arg(long)]
+        list: bool,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Launch the graphical interface.
+    Gui,
+}
+
+/// Arguments shared by the subcommands that organize a directory.
+#[derive(Args)]
+pub struct OrganizeArgs {
+    /// The directory to organize.
+    pub directory: PathBuf,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+/// Configuration options. Flags override values read from configuration files.
+#[derive(Args)]
+pub struct ConfigArgs {
+    /// Read settings from this TOML file instead of the user configuration file.
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Copy files instead of moving them.
+    #[arg(long, overrides_with = "move_files")]
+    pub copy: bool,
+    /// Move files instead of copying them.
+    #[arg(long = "move", overrides_with = "copy")]
+    pub move_files: bool,
+    /// Include hidden files.
+    #[arg(long, overrides_with = "exclude_hidden")]
+    pub include_hidden: bool,
+    /// Leave hidden files alone.
+    #[arg(long, overrides_with = "include_hidden")]
+    pub exclude_hidden: bool,
+    /// Leave files and directories matching this glob pattern alone. May be repeated.
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+    /// Only organize files matching this glob pattern. May be repeated.
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+    /// Leave files smaller than this many bytes alone.
+    #[arg(long, value_name = "BYTES")]
+    pub min_size: Option<u64>,
+    /// Leave files larger than this many bytes alone.
+    #[arg(long, value_name = "BYTES")]
+    pub max_size: Option<u64>,
+    /// Leave files younger than this many days alone.
+    #[arg(long, value_name = "DAYS")]
+    pub min_age: Option<u32>,
+    /// Leave files older than this many days alone.
+    #[arg(long, value_name = "DAYS")]
+    pub max_age: Option<u32>,
+    /// Timestamp file ages are measured from (modified, accessed).
+    #[arg(long, value_name = "SOURCE")]
+    pub age_source: Option<AgeSource>,
+    /// Organize subdirectories as well.
+    #[arg(long, overrides_with = "no_recursive")]
+    pub recursive: bool,
+    /// Leave subdirectories alone.
+    #[arg(long, overrides_with = "recursive")]
+    pub no_recursive: bool,
+    /// How many levels of subdirectories to organize.
+    #[arg(long, value_name = "DEPTH")]
+    pub max_depth: Option<usize>,
+    /// How subdirectories are organized (flatten, in_place).
+    #[arg(long, value_name = "LAYOUT")]
+    pub layout: Option<RecursiveLayout>,
+    /// What to do with project directories, such as git checkouts, found when
+    /// organizing recursively (skip, move).
+    #[arg(long, value_name = "POLICY")]
+    pub projects: Option<ProjectPolicy>,
+    /// Directory name project directories are moved to with `--projects move`.
+    #[arg(long, value_name = "NAME")]
+    pub projects_directory: Option<String>,
+    /// Treat directories containing a file or directory with this name as
+    /// projects, in addition to the configured markers. May be repeated.
+    #[arg(long, value_name = "NAME")]
+    pub project_marker: Vec<String>,
+    /// What to do with symbolic links (skip, move_link, follow).
+    #[arg(long, value_name = "POLICY")]
+    pub symlinks: Option<SymlinkPolicy>,
+    /// Create the organized folders in this directory instead of the organized one.
+    #[arg(long, value_name = "DIR")]
+    pub destination_root: Option<String>,
+    /// Create the organized folders inside the organized directory, even if a
+    /// configuration file sets a destination root.
+    #[arg(long, conflicts_with = "destination_root")]
+    pub in_place: bool,
+    /// Destination folder template, e.g. "{category}/{year}/{first_letter}".
+    /// Overrides categories and date folders.
+    #[arg(long, value_name = "TEMPLATE")]
+    pub template: Option<Template>,
+    /// Do not use a destination template, even if a configuration file sets one.
+    #[arg(long, conflicts_with = "template")]
+    pub no_template: bool,
+    /// Organize files into date folders, taking each file's date from
+    /// (modified, created, filename).
+    #[arg(long, value_name = "SOURCE")]
+    pub date_source: Option<DateSource>,
+    /// Date folder layout, e.g. "{year}/{month:02}". Implies organizing by date.
+    #[arg(long, value_name = "PATTERN")]
+    pub date_pattern: Option<Template>,
+    /// Put date folders inside category folders. Implies organizing by date.
+    #[arg(long)]
+    pub date_with_category: bool,
+    /// Do not organize by date, even if a configuration file says so.
+    #[arg(long, conflicts_with_all = ["date_source", "date_pattern", "date_with_category"])]
+    pub no_date: bool,
+    /// Organize sidecar files such as .xmp edits and subtitles on their own
+    /// instead of keeping them with their primary file.
+    #[arg(long)]
+    pub no_sidecars: bool,
+    /// Directory name for files without an extension.
+    #[arg(long, value_name = "NAME")]
+    pub others_directory: Option<String>,
+    /// When to detect file types from their content (off, fallback, override).
+    #[arg(long, value_name = "MODE")]
+    pub content_detection: Option<ContentDetection>,
+    /// Path of the log file.
+    #[arg(long, value_name = "FILE")]
+    pub log_file: Option<String>,
+    /// Verify copied files by checksum before removing anything.
+    #[arg(long, overrides_with = "no_verify")]
+    pub verify: bool,
+    /// Do not verify copied files.
+    #[arg(long, overrides_with = "verify")]
+    pub no_verify: bool,
+    /// Carry on with the remaining files when one cannot be organized.
+    #[arg(long, overrides_with = "stop_on_error")]
+    pub continue_on_error: bool,
+    /// Stop at the first file that cannot be organized.
+    #[arg(long, overrides_with = "continue_on_error")]
+    pub stop_on_error: bool,
+    /// What to do when a destination already exists
+    /// (skip, overwrite, rename_numbered, rename_timestamp, keep_newer, keep_larger, dedupe).
+    #[arg(long, value_name = "POLICY")]
+    pub conflict_policy: Option<ConflictPolicy>,
+    /// What to do with files identical to another file being organized
+    /// (ignore, report, move, hard_link).
+    #[arg(long, value_name = "POLICY")]
+    pub duplicates: Option<DuplicatePolicy>,
+    /// Directory name duplicates are moved to with `--duplicates move`.
+    #[arg(long, value_name = "NAME")]
+    pub duplicates_directory: Option<String>,
+    /// Directory where run journals are stored.
+    #[arg(long, value_name = "DIR")]
+    pub journal_directory: Option<String>,
+    /// Organize even protected locations such as `/`, system directories or the
+    /// home directory itself.
+    #[arg(long)]
+    pub force: bool,
+    /// Organize a directory even if it is itself a project.
+    #[arg(long)]
+    pub allow_project: bool,
+}
+
+impl ConfigArgs {
+    /// Builds the effective configuration: the configuration files that apply to
+    /// `directory` (see [`Config::discover`]) with any flags given on the command
+    /// line applied on top.
+    pub fn load(&self, directory: Option<&Path>) -> Result<Config, Box<dyn Error>> {
+        let mut config = Config::discover(directory, self.config.as_deref())?;
+
+        if self.copy {
+            config.copy = true;
+        }
+        if self.move_files {
+            config.copy = false;
+        }
+        if self.include_hidden {
+            config.include_hidden = true;
+        }
+        if self.exclude_hidden {
+            config.include_hidden = false;
+        }
+        config.exclude.extend(self.exclude.iter().cloned());
+        config.include.extend(self.include.iter().cloned());
+        if let Some(size) = self.min_size {
+            config.min_size = Some(size);
+        }
+        if let Some(size) = self.max_size {
+            config.max_size = Some(size);
+        }
+        if let Some(days) = self.min_age {
+            config.min_age_days = Some(days);
+        }
+        if let Some(days) = self.max_age {
+            config.max_age_days = Some(days);
+        }
+        if let Some(source) = self.age_source {
+            config.age_source = source;
+        }
+        if self.recursive {
+            config.recursive = true;
+        }
+        if self.no_recursive {
+            config.recursive = false;
+        }
+        if let Some(max_depth) = self.max_depth {
+            config.max_depth = Some(max_depth);
+        }
+        if let Some(layout) = self.layout {
+            config.recursive_layout = layout;
+        }
+        if let Some(policy) = self.projects {
+            config.project_policy = policy;
+        }
+        if let Some(projects_directory) = &self.projects_directory {
+            config.projects_directory = projects_directory.clone();
+        }
+        config.project_markers.extend(self.project_marker.iter().cloned());
+        if let Some(policy) = self.symlinks {
+            config.symlink_policy = policy;
+        }
+        if self.in_place {
+            config.destination_root = None;
+        }
+        if let Some(destination_root) = &self.destination_root {
+            config.destination_root = Some(destination_root.clone());
+        }
+        if self.no_template {
+            config.destination_template = None;
+        }
+        if let Some(template) = &self.template {
+            config.destination_template = Some(template.clone());
+        }
+        if self.no_date {
+            config.date_layout = None;
+        }
+        if self.no_sidecars {
+            config.sidecars.clear();
+        }
+        if self.date_source.is_some() || self.date_pattern.is_some() || self.date_with_category {
+            let layout = config.date_layout.get_or_insert_with(DateLayout::default);
+            if let Some(source) = self.date_source {
+                layout.source = source;
+            }
+            if let Some(pattern) = &self.date_pattern {
+                layout.pattern = pattern.clone();
+            }
+            if self.date_with_category {
+                layout.with_category = true;
+            }
+        }
+        if let Some(others_directory) = &self.others_directory {
+            config.others_directory = others_directory.clone();
+        }
+        if let Some(content_detection) = self.content_detection {
+            config.content_detection = content_detection;
+        }
+        if let Some(log_file) = &self.log_file {
+            config.log_file = log_file.clone();
+        }
+        if self.verify {
+            config.verify_transfers = true;
+        }
+        if self.no_verify {
+            config.verify_transfers = false;
+        }
+        if self.continue_on_error {
+            config.continue_on_error = true;
+        }
+        if self.stop_on_error {
+            config.continue_on_error = false;
+        }
+        if let Some(conflict_policy) = self.conflict_policy {
+            config.conflict_policy = conflict_policy;
+        }
+        if let Some(policy) = self.duplicates {
+            config.duplicate_policy = policy;
+        }
+        if let Some(duplicates_directory) = &self.duplicates_directory {
+            config.duplicates_directory = duplicates_directory.clone();
+        }
+        if let Some(journal_directory) = &self.journal_directory {
+            config.journal_directory = journal_directory.clone();
+        }
+        if self.force {
+            config.allow_protected_directories = true;
+        }
+        if self.allow_project {
+            config.allow_project_root = true;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Runs the command described by `cli`.
+///
+/// # Errors
+///
+/// Returns an error if the configuration is invalid or the command fails.
+pub fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        None | Some(Command::Gui) => gui::run_gui()?,
+        Some(Command::Organize { organize, json, yes }) => {
+            let config = organize.config.load(Some(&organize.directory))?;
+            let plan = plan_organisation(&organize.directory.to_string_lossy(), &config)?;
+            if !yes && plan.needs_confirmation(&config) && !confirm(&plan)? {
+                return Err("cancelled, nothing was changed".into());
+            }
+            // Ctrl-C stops the run after the current file, keeping it undoable.
+            let cancel = CancellationToken::new();
+            let handler_token = cancel.clone();
+            ctrlc::set_handler(move || handler_token.cancel())?;
+
+            let report = apply_plan_with(&plan, &config, &mut TerminalProgress::new(), &cancel)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("{}", report);
+            }
+            if !report.failures.is_empty() {
+                for failure in &report.failures {
+                    eprintln!("Failed: {}", failure);
+                }
+                return Err(format!("{} items could not be organized", report.failures.len()).into());
+            }
+            if report.cancelled {
+                return Err(format!(
+                    "cancelled after {} of {} files; run `fileorg undo {}` to reverse them",
+                    report.operations.len(),
+                    plan.operations.len(),
+                    report.run_id
+                )
+                .into());
+            }
+        }
+        Some(Command::Watch(args)) => {
+            let config = args.config.load(Some(&args.directory))?;
+            let cancel = CancellationToken::new();
+            let handler_token = cancel.clone();
+            ctrlc::set_handler(move || handler_token.cancel())?;
+
+            println!("Watching {}. Press Ctrl-C to stop.", args.directory.display());
+            watch_directory(&args.directory.to_string_lossy(), &config, &cancel, &mut |result| match result {
+                Ok(report) => {
+                    println!(
+                        "[{}] {} files organized, {} failed. Run id: {}",
+                        Local::now().format("%H:%M:%S"),
+                        report.operations.len(),
+                        report.failures.len(),
+                        report.run_id
+                    );
+                    for failure in &report.failures {
+                        eprintln!("Failed: {}", failure);
+                    }
+                }
+                Err(err) => eprintln!("[{}] Error: {}", Local::now().format("%H:%M:%S"), err),
+            })?;
+        }
+        Some(Command::Plan { organize, json }) => {
+            let config = organize.config.load(Some(&organize.directory))?;
+            let plan = plan_organisation(&organize.directory.to_string_lossy(), &config)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&plan)?);
+            } else {
+                println!("{}", plan);
+            }
+        }
+        Some(Command::Undo { run_id, list, config }) => {
+            let config = config.load(None)?;
+            if list {
+                for run in list_runs(&config)? {
+                    let undone = read_journal(&run.run_id, &config)?.is_undone();
+                    println!(
+                        "{}  {}  {}{}",
+                        run.run_id,
+                        run.timestamp,
+                        run.root.display(),
+                        if undone { "  (undone)" } else { "" }
+                    );
+                }
+                return Ok(());
+            }
+
+            let run_id = match run_id {
+                Some(run_id) => run_id,
+                None => latest_run(&config)?.ok_or("no run to undo")?,
+            };
+            undo_run(&run_id, &config)?;
+            println!("Run {} undone.", run_id);
+        }
+    }
+    Ok(())
+}
+
+/// Asks on the terminal whether to go ahead with a large `plan`.
+///
+/// # Errors
+///
+/// Returns an error if there is no terminal to ask on, so that scripts must
+/// pass `--yes` explicitly.
+fn confirm(plan: &Plan) -> Result<bool, Box<dyn Error>> {
+    if !io::stdin().is_terminal() {
+        return Err(format!(
+            "refusing to organize {} files without confirmation; pass --yes to go ahead",
+            plan.operations.len()
+        )
+        .into());
+    }
+    print!("About to organize {} files in {}. Continue? [y/N] ", plan.operations.len(), plan.root.display());
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// The most recent run that has not been undone yet.
+fn latest_run(config: &Config) -> std::io::Result<Option<String>> {
+    for run in list_runs(config)?.into_iter().rev() {
+        if !read_journal(&run.run_id, config)?.is_undone() {
+            return Ok(Some(run.run_id));
+        }
+    }
+    Ok(None)
+}
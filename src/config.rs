@@ -0,0 +1,608 @@
+//! Configuration options and configuration file handling.
+//!
+//! Settings are read from TOML files. [`Config::discover`] layers, from lowest
+//! to highest precedence: the built-in defaults, the user configuration file
+//! (`$XDG_CONFIG_HOME/fileorg/config.toml` on Linux, or an explicitly given
+//! path), and a `.fileorg.toml` file in the directory being organized. Every
+//! file may set any subset of the options, except that a directory's own file
+//! may not set the options in [`USER_ONLY_KEYS`] or choose to overwrite files.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::category::{default_categories, default_compound_extensions, CategoryRule};
+use crate::filter;
+use crate::project::{default_project_markers, ProjectPolicy};
+use crate::sidecar::{default_sidecar_rules, SidecarRule};
+use crate::{
+    AgeSource, ConflictPolicy, ContentDetection, DuplicatePolicy, DateLayout, RecursiveLayout, SymlinkPolicy, Template,
+    WatchSettings,
+};
+
+/// Name of the per-directory configuration file.
+pub const DIRECTORY_CONFIG_FILE: &str = ".fileorg.toml";
+
+/// Options a directory's own configuration file may not set, because they
+/// would let a file inside the organized directory switch off a safety check,
+/// write outside of the directory or reach files elsewhere.
+pub const USER_ONLY_KEYS: [&str; 7] = [
+    "allow_protected_directories",
+    "allow_project_root",
+    "confirmation_threshold",
+    "destination_root",
+    "log_file",
+    "journal_directory",
+    "symlink_policy",
+];
+
+/// Configuration options for organizing files.
+/// Users can specify whether to copy files instead of moving them,
+/// which files to include or exclude by name, size or age, whether to include
+/// hidden files and subdirectories, where organized folders are created, the
+/// name of the directory to store files without extensions, whether file types
+/// are detected from content, how to resolve name conflicts, what to do with
+/// duplicate files, the path to the log file, where run journals are kept, the
+/// destination folder layout, which files and project directories stay
+/// together, and which extensions are grouped into categories.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// If true, files will be copied; otherwise, they will be moved.
+    pub copy: bool,
+    /// If true, hidden files will be included.
+    pub include_hidden: bool,
+    /// Glob patterns of files and directories to leave alone, e.g. `*.tmp` or
+    /// `desktop.ini`. Patterns containing `/` match the path relative to the
+    /// organized directory, others the name.
+    pub exclude: Vec<String>,
+    /// If not empty, only files matching one of these glob patterns are organized.
+    pub include: Vec<String>,
+    /// Files smaller than this many bytes are left alone.
+    pub min_size: Option<u64>,
+    /// Files larger than this many bytes are left alone.
+    pub max_size: Option<u64>,
+    /// Files younger than this many days are left alone, e.g. `7` to sweep only
+    /// files that have not been touched for a week.
+    pub min_age_days: Option<u32>,
+    /// Files older than this many days are left alone.
+    pub max_age_days: Option<u32>,
+    /// Which timestamp the age limits are measured from.
+    pub age_source: AgeSource,
+    /// If true, subdirectories are organized as well.
+    pub recursive: bool,
+    /// How many levels of subdirectories to organize when recursive. Unlimited if unset.
+    pub max_depth: Option<usize>,
+    /// Whether subdirectories are gathered into the top-level folders or organized in place.
+    pub recursive_layout: RecursiveLayout,
+    /// Names of files or directories, such as `.git` or `Cargo.toml`, marking a
+    /// project directory that is never split up when organizing recursively.
+    pub project_markers: Vec<String>,
+    /// What to do with project directories found when organizing recursively.
+    pub project_policy: ProjectPolicy,
+    /// Directory name where project directories are moved to with the `move` project policy.
+    pub projects_directory: String,
+    /// What to do with symbolic links in the organized directory.
+    pub symlink_policy: SymlinkPolicy,
+    /// Directory the organized folders are created in, which may be on another
+    /// file system. If unset, they are created inside the organized directory.
+    pub destination_root: Option<String>,
+    /// Directory name where files without extensions will be stored.
+    pub others_directory: String,
+    /// Whether file contents are inspected to determine file types.
+    pub content_detection: ContentDetection,
+    /// Extensions made of several parts, such as `tar.gz`, that are recognised
+    /// as a single extension (without the leading dot).
+    pub compound_extensions: Vec<String>,
+    /// Path to the log file where operations are recorded.
+    pub log_file: String,
+    /// If true, every file copied (including moves across file systems) is read
+    /// back and compared with the original by checksum before the source is removed.
+    pub verify_transfers: bool,
+    /// If true, a file or directory that cannot be organized is recorded in the
+    /// run report and the run carries on; otherwise the first failure ends it.
+    pub continue_on_error: bool,
+    /// What to do when a file's destination already exists.
+    pub conflict_policy: ConflictPolicy,
+    /// What to do with files whose content is identical to another file being organized.
+    pub duplicate_policy: DuplicatePolicy,
+    /// Directory name where duplicates are moved to with the `move` duplicate policy.
+    pub duplicates_directory: String,
+    /// Directory where machine-readable run journals are stored for undo. The
+    /// default is inside the user's local data directory, such as
+    /// `~/.local/share/fileorg/journal` on Linux, so that every run can be
+    /// undone wherever it was started from.
+    pub journal_directory: String,
+    /// If true, protected locations such as `/`, system directories and the
+    /// home directory itself may be organized.
+    pub allow_protected_directories: bool,
+    /// If true, a directory that is itself a project (one containing one of
+    /// `project_markers`) may be organized.
+    pub allow_project_root: bool,
+    /// Interactive runs transferring more files than this ask for confirmation
+    /// first. Never asks if 0.
+    pub confirmation_threshold: usize,
+    /// If set, decides each file's destination folder, overriding categories and
+    /// the date layout, e.g. `{category}/{year}/{first_letter}`. Date placeholders
+    /// use the date layout's date source, or the modification time if there is none.
+    pub destination_template: Option<Template>,
+    /// If set, files are organized into date folders.
+    pub date_layout: Option<DateLayout>,
+    /// Settings for watch mode.
+    pub watch: WatchSettings,
+    /// Rules keeping sidecar files, such as `.xmp` edits or subtitles, with
+    /// their primary file. An empty list organizes every file on its own.
+    pub sidecars: Vec<SidecarRule>,
+    /// Rules grouping extensions into category folders. Extensions not listed
+    /// in any rule get a folder named after the extension. An empty list
+    /// gives every extension its own folder.
+    pub categories: Vec<CategoryRule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            copy: false,
+            include_hidden: false,
+            exclude: Vec::new(),
+            include: Vec::new(),
+            min_size: None,
+            max_size: None,
+            min_age_days: None,
+            max_age_days: None,
+            age_source: AgeSource::default(),
+            recursive: false,
+            max_depth: None,
+            recursive_layout: RecursiveLayout::default(),
+            project_markers: default_project_markers(),
+            project_policy: ProjectPolicy::default(),
+            projects_directory: "Projects".to_owned(),
+            symlink_policy: SymlinkPolicy::default(),
+            destination_root: None,
+            others_directory: "Others".to_owned(),
+            content_detection: ContentDetection::default(),
+            compound_extensions: default_compound_extensions(),
+            log_file: "file_organizer.log".to_owned(),
+            verify_transfers: false,
+            continue_on_error: false,
+            conflict_policy: ConflictPolicy::default(),
+            duplicate_policy: DuplicatePolicy::default(),
+            duplicates_directory: "Duplicates".to_owned(),
+            journal_directory: default_journal_directory(),
+            allow_protected_directories: false,
+            allow_project_root: false,
+            confirmation_threshold: 1000,
+            destination_template: None,
+            date_layout: None,
+            watch: WatchSettings::default(),
+            sidecars: default_sidecar_rules(),
+            categories: default_categories(),
+        }
+    }
+}
+
+/// An error reading, validating or writing a configuration file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read or written.
+    Io {
+        /// The file being accessed.
+        path: PathBuf,
+        /// The underlying error.
+        source: io::Error,
+    },
+    /// The file is not valid TOML or contains a value of the wrong type.
+    Parse {
+        /// The file being parsed.
+        path: PathBuf,
+        /// The offending key, if it could be determined.
+        key: Option<String>,
+        /// What is wrong.
+        message: String,
+    },
+    /// A value is well-formed but not allowed.
+    Invalid {
+        /// The offending key.
+        key: String,
+        /// What is wrong.
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ConfigError::Parse { path, key: Some(key), message } => {
+                write!(f, "{}: error in `{}`: {}", path.display(), key, message)
+            }
+            ConfigError::Parse { path, key: None, message } => write!(f, "{}: {}", path.display(), message),
+            ConfigError::Invalid { key, message } => write!(f, "invalid value for `{}`: {}", key, message),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl Config {
+    /// Path of the user configuration file, e.g. `~/.config/fileorg/config.toml`.
+    pub fn user_config_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|directory| directory.join("fileorg").join("config.toml"))
+    }
+
+    /// Reads a single configuration file; options it does not set keep their defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` if the file cannot be read, is malformed, or fails validation.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let mut table = toml::Table::new();
+        merge_file(&mut table, path)?;
+        Config::from_table(table)
+    }
+
+    /// Finds and layers all configuration files that apply to `directory`.
+    ///
+    /// `override_path` replaces the user configuration file when given, and
+    /// must exist. The user configuration file and the per-directory
+    /// `.fileorg.toml` are only read if they exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` if a file cannot be read, is malformed, the
+    /// per-directory file sets one of [`USER_ONLY_KEYS`] or the `overwrite`
+    /// conflict policy, or the combined configuration fails validation.
+    pub fn discover(directory: Option<&Path>, override_path: Option<&Path>) -> Result<Config, ConfigError> {
+        let mut table = toml::Table::new();
+
+        match override_path {
+            Some(path) => merge_file(&mut table, path)?,
+            None => {
+                if let Some(path) = Config::user_config_path().filter(|path| path.is_file()) {
+                    merge_file(&mut table, &path)?;
+                }
+            }
+        }
+
+        if let Some(path) = directory.map(|d| d.join(DIRECTORY_CONFIG_FILE)).filter(|path| path.is_file()) {
+            // A directory must not be able to vouch for itself.
+            let layer = read_layer(&path)?;
+            if let Some(key) = USER_ONLY_KEYS.into_iter().find(|key| layer.contains_key(*key)) {
+                return Err(ConfigError::Parse {
+                    path,
+                    key: Some(key.to_owned()),
+                    message: "can only be set in the user configuration file or on the command line".to_owned(),
+                });
+            }
+            if layer.get("conflict_policy").and_then(toml::Value::as_str) == Some(ConflictPolicy::Overwrite.name()) {
+                return Err(ConfigError::Parse {
+                    path,
+                    key: Some("conflict_policy".to_owned()),
+                    message: "can only be `overwrite` in the user configuration file or on the command line"
+                        .to_owned(),
+                });
+            }
+            table.extend(layer);
+        }
+
+        Config::from_table(table)
+    }
+
+    /// Returns this configuration with every option in which `changed` differs
+    /// from `base` taken from `changed`, the way command-line flags are layered
+    /// on top of the configuration files.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` if the combined configuration fails validation.
+    pub fn with_changes(&self, base: &Config, changed: &Config) -> Result<Config, ConfigError> {
+        let table = |config: &Config| toml::Table::try_from(config).expect("a configuration always serializes");
+        let (base, changed) = (table(base), table(changed));
+        let mut merged = table(self);
+
+        // Options that are unset are left out, so a removed key was cleared.
+        for key in base.keys().filter(|key| !changed.contains_key(*key)) {
+            merged.remove(key);
+        }
+        for (key, value) in changed {
+            if base.get(&key) != Some(&value) {
+                merged.insert(key, value);
+            }
+        }
+
+        Config::from_table(merged)
+    }
+
+    /// Writes the configuration to `path` as TOML, creating parent directories as needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` if the file cannot be written.
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        let io_error = |source| ConfigError::Io { path: path.to_path_buf(), source };
+        let text = toml::to_string_pretty(self).map_err(|e| io_error(io::Error::other(e)))?;
+
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        fs::write(path, text).map_err(io_error)
+    }
+
+    /// Checks that every option holds a usable value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConfigError::Invalid` naming the first offending key.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |key: &str, message: &str| ConfigError::Invalid {
+            key: key.to_owned(),
+            message: message.to_owned(),
+        };
+        let mut categorized: Vec<(String, &str)> = Vec::new();
+
+        if self.destination_root.as_ref().is_some_and(|root| root.trim().is_empty()) {
+            return Err(invalid("destination_root", "must not be empty"));
+        }
+        let is_plain_name = |name: &str| {
+            let name = name.trim();
+            !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\'])
+        };
+        if !is_plain_name(&self.others_directory) {
+            return Err(invalid("others_directory", "must be a plain folder name"));
+        }
+        if !is_plain_name(&self.duplicates_directory) {
+            return Err(invalid("duplicates_directory", "must be a plain folder name"));
+        }
+        if !is_plain_name(&self.projects_directory) {
+            return Err(invalid("projects_directory", "must be a plain folder name"));
+        }
+        if !self.project_markers.iter().all(|marker| is_plain_name(marker)) {
+            return Err(invalid("project_markers", "markers must be plain file or directory names"));
+        }
+        if self.log_file.trim().is_empty() {
+            return Err(invalid("log_file", "must not be empty"));
+        }
+        if self.journal_directory.trim().is_empty() {
+            return Err(invalid("journal_directory", "must not be empty"));
+        }
+
+        for (key, patterns) in [("exclude", &self.exclude), ("include", &self.include)] {
+            for (i, pattern) in patterns.iter().enumerate() {
+                if let Err(err) = filter::compile(pattern) {
+                    return Err(invalid(&format!("{}[{}]", key, i), &err.kind().to_string()));
+                }
+            }
+        }
+
+        if let (Some(min), Some(max)) = (self.min_size, self.max_size) {
+            if min > max {
+                return Err(invalid("max_size", "must not be less than min_size"));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_age_days, self.max_age_days) {
+            if min > max {
+                return Err(invalid("max_age_days", "must not be less than min_age_days"));
+            }
+        }
+
+        if self
+            .compound_extensions
+            .iter()
+            .any(|e| e.starts_with('.') || e.ends_with('.') || !e.contains('.') || e.contains(['/', '\\']))
+        {
+            return Err(invalid(
+                "compound_extensions",
+                "extensions must contain a dot and be listed without the leading dot",
+            ));
+        }
+
+        if self.watch.partial_extensions.iter().any(|e| e.is_empty() || e.starts_with('.')) {
+            return Err(invalid(
+                "watch.partial_extensions",
+                "extensions must be non-empty and listed without the leading dot",
+            ));
+        }
+
+        for (i, rule) in self.sidecars.iter().enumerate() {
+            for (field, extensions) in [
+                ("primary_extensions", &rule.primary_extensions),
+                ("sidecar_extensions", &rule.sidecar_extensions),
+            ] {
+                let key = format!("sidecars[{}].{}", i, field);
+                if extensions.is_empty() {
+                    return Err(invalid(&key, "must list at least one extension"));
+                }
+                if extensions.iter().any(|e| e.is_empty() || e.starts_with('.')) {
+                    return Err(invalid(&key, "extensions must be non-empty and listed without the leading dot"));
+                }
+            }
+        }
+
+        for (i, rule) in self.categories.iter().enumerate() {
+            let name = rule.name.trim();
+            if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+                return Err(invalid(
+                    &format!("categories[{}].name", i),
+                    "must be a plain folder name",
+                ));
+            }
+            for extension in &rule.extensions {
+                let key = format!("categories[{}].extensions", i);
+                if extension.is_empty() || extension.starts_with('.') {
+                    return Err(invalid(&key, "extensions must be non-empty and listed without the leading dot"));
+                }
+                let extension = extension.to_lowercase();
+                if let Some((_, other)) = categorized.iter().find(|(e, _)| *e == extension) {
+                    return Err(invalid(
+                        &key,
+                        &format!("extension `{}` is already in category `{}`", extension, other),
+                    ));
+                }
+                categorized.push((extension, &rule.name));
+            }
+        }
+        Ok(())
+    }
+
+    fn from_table(table: toml::Table) -> Result<Config, ConfigError> {
+        // Each layer was deserialized on its own before being merged, and merging
+        // only replaces whole top-level values, so the result is always well-formed.
+        let config: Config = table.try_into().expect("every configuration layer was checked");
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// The journal directory inside the user's local data directory, or
+/// `fileorg_journal` in the working directory if there is none.
+fn default_journal_directory() -> String {
+    dirs::data_local_dir()
+        .map(|directory| directory.join("fileorg").join("journal").to_string_lossy().into_owned())
+        .unwrap_or_else(|| "fileorg_journal".to_owned())
+}
+
+/// Reads the configuration file at `path`, checks it, and merges its keys into `table`.
+fn merge_file(table: &mut toml::Table, path: &Path) -> Result<(), ConfigError> {
+    table.extend(read_layer(path)?);
+    Ok(())
+}
+
+/// Reads the configuration file at `path`, checks it, and returns its keys.
+fn read_layer(path: &Path) -> Result<toml::Table, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    // Deserialize the file on its own first so that errors point at the file and key.
+    if let Err(e) = toml::from_str::<Config>(&text) {
+        return Err(ConfigError::Parse {
+            path: path.to_path_buf(),
+            key: e.span().and_then(|span| key_at(&text, span.start)),
+            message: e.message().to_owned(),
+        });
+    }
+
+    Ok(text.parse().expect("already parsed successfully"))
+}
+
+/// Works out the dotted key of the line containing byte `offset`, for error messages.
+fn key_at(text: &str, offset: usize) -> Option<String> {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = text[line_start..].lines().next().unwrap_or("");
+    let key = line.split('=').next()?.trim();
+    if key.is_empty() || key.starts_with('[') {
+        return None;
+    }
+
+    // Prefix the key with the table it belongs to, if any.
+    let table = before[..line_start]
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|line| line.starts_with('['))
+        .map(|header| header.trim_matches(|c| c == '[' || c == ']').trim().to_owned());
+
+    Some(match table {
+        Some(table) => format!("{}.{}", table, key),
+        None => key.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_top_level_keys() {
+        let text = "copy = true\nrecursive = maybe\n";
+        assert_eq!(key_at(text, 0).as_deref(), Some("copy"));
+        assert_eq!(key_at(text, text.find("maybe").unwrap()).as_deref(), Some("recursive"));
+    }
+
+    #[test]
+    fn prefixes_keys_with_their_table() {
+        let text = "copy = true\n\n[ watch ]\ndebounce_seconds = \"soon\"\n";
+        let offset = text.find("\"soon\"").unwrap();
+        assert_eq!(key_at(text, offset).as_deref(), Some("watch.debounce_seconds"));
+    }
+
+    #[test]
+    fn has_no_key_for_headers_or_blank_lines() {
+        let text = "[watch]\n\n";
+        assert_eq!(key_at(text, 0), None);
+        assert_eq!(key_at(text, text.len()), None);
+        assert_eq!(key_at(text, usize::MAX), None);
+    }
+
+    #[test]
+    fn layers_the_directory_configuration_over_the_user_configuration() {
+        let scratch = tempfile::tempdir().unwrap();
+        let directory = scratch.path();
+        let user = directory.join("user.toml");
+        fs::write(&user, "copy = true\nrecursive = false\n").unwrap();
+        fs::write(directory.join(DIRECTORY_CONFIG_FILE), "recursive = true\n").unwrap();
+
+        let config = Config::discover(Some(directory), Some(&user)).unwrap();
+        assert!(config.copy);
+        assert!(config.recursive);
+    }
+
+    #[test]
+    fn keeps_user_only_settings_out_of_directory_configurations() {
+        let scratch = tempfile::tempdir().unwrap();
+        let directory = scratch.path();
+        let user = directory.join("user.toml");
+        fs::write(&user, "").unwrap();
+
+        for layer in [
+            "allow_protected_directories = true",
+            "log_file = \"elsewhere.log\"",
+            "conflict_policy = \"overwrite\"",
+            "symlink_policy = \"follow\"",
+        ] {
+            fs::write(directory.join(DIRECTORY_CONFIG_FILE), layer).unwrap();
+            let result = Config::discover(Some(directory), Some(&user));
+            assert!(matches!(result, Err(ConfigError::Parse { .. })), "{} should be rejected", layer);
+        }
+    }
+
+    #[test]
+    fn keeps_journals_in_one_place_by_default() {
+        if let Some(data) = dirs::data_local_dir() {
+            let journal_directory = PathBuf::from(Config::default().journal_directory);
+            assert!(journal_directory.is_absolute());
+            assert!(journal_directory.starts_with(data));
+        }
+    }
+
+    #[test]
+    fn applies_only_changed_options() {
+        let base = Config::default();
+        let loaded = Config { recursive: true, others_directory: "Misc".to_owned(), ..Config::default() };
+
+        let unchanged = loaded.with_changes(&base, &base).unwrap();
+        assert!(unchanged.recursive && !unchanged.copy);
+        assert_eq!(unchanged.others_directory, "Misc");
+
+        let changed = Config { copy: true, others_directory: "Rest".to_owned(), ..base.clone() };
+        let merged = loaded.with_changes(&base, &changed).unwrap();
+        assert!(merged.recursive && merged.copy);
+        assert_eq!(merged.others_directory, "Rest");
+
+        let base = Config { min_age_days: Some(3), ..Config::default() };
+        let loaded = Config { min_age_days: Some(7), ..Config::default() };
+        assert_eq!(loaded.with_changes(&base, &Config::default()).unwrap().min_age_days, None);
+    }
+}
@@ -0,0 +1,305 @@
+//! Policies for files whose destination is already taken.
+//!
+//! When a file would be moved or copied onto a path that already exists, the
+//! configured [`ConflictPolicy`] decides what happens instead of silently
+//! overwriting the existing file. Every decision is recorded in the plan as a
+//! [`ConflictDecision`] and written to the log when the plan is applied.
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::path::{Path, PathBuf};
+
+use crate::category::split_extension;
+use crate::digest::same_content;
+use crate::plan::SkipReason;
+
+/// What to do when a file's destination already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Leave the file where it is.
+    Skip,
+    /// Replace the existing file. The replaced file cannot be restored by undo.
+    Overwrite,
+    /// Add a numeric suffix, e.g. `report (1).pdf`.
+    #[default]
+    RenameNumbered,
+    /// Add the file's modification time as a suffix, e.g. `report_20240131-094500.pdf`.
+    RenameTimestamp,
+    /// Replace the existing file only if the incoming file was modified more recently.
+    KeepNewer,
+    /// Replace the existing file only if the incoming file is larger.
+    KeepLarger,
+    /// Leave the file where it is if its content is identical to the existing file,
+    /// otherwise add a numeric suffix.
+    Dedupe,
+}
+
+impl ConflictPolicy {
+    /// Every policy, in the order they are offered to users.
+    pub const ALL: [ConflictPolicy; 7] = [
+        ConflictPolicy::Skip,
+        ConflictPolicy::Overwrite,
+        ConflictPolicy::RenameNumbered,
+        ConflictPolicy::RenameTimestamp,
+        ConflictPolicy::KeepNewer,
+        ConflictPolicy::KeepLarger,
+        ConflictPolicy::Dedupe,
+    ];
+
+    /// The name used for the policy in configuration files and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Skip => "skip",
+            ConflictPolicy::Overwrite => "overwrite",
+            ConflictPolicy::RenameNumbered => "rename_numbered",
+            ConflictPolicy::RenameTimestamp => "rename_timestamp",
+            ConflictPolicy::KeepNewer => "keep_newer",
+            ConflictPolicy::KeepLarger => "keep_larger",
+            ConflictPolicy::Dedupe => "dedupe",
+        }
+    }
+
+    /// A short human-readable description of the policy.
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Skip => "Skip",
+            ConflictPolicy::Overwrite => "Overwrite",
+            ConflictPolicy::RenameNumbered => "Rename with number",
+            ConflictPolicy::RenameTimestamp => "Rename with timestamp",
+            ConflictPolicy::KeepNewer => "Keep newer",
+            ConflictPolicy::KeepLarger => "Keep larger",
+            ConflictPolicy::Dedupe => "Deduplicate by content",
+        }
+    }
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ConflictPolicy::ALL
+            .into_iter()
+            .find(|policy| policy.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<_> = ConflictPolicy::ALL.iter().map(|policy| policy.name()).collect();
+                format!("unknown conflict policy '{}', expected one of: {}", s, names.join(", "))
+            })
+    }
+}
+
+/// How a single conflict was resolved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum ConflictOutcome {
+    /// The incoming file is left where it is.
+    Skipped,
+    /// The existing file will be replaced.
+    Overwritten,
+    /// The incoming file will be given a different name.
+    Renamed(PathBuf),
+    /// The incoming file is identical to the existing one and is left where it is.
+    Duplicate,
+}
+
+/// A conflict found while planning, together with how it was resolved.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConflictDecision {
+    /// The file being organized.
+    pub source: PathBuf,
+    /// The destination that was already taken.
+    pub destination: PathBuf,
+    /// The policy that decided the outcome.
+    pub policy: ConflictPolicy,
+    /// What will happen to the file.
+    pub outcome: ConflictOutcome,
+}
+
+impl fmt::Display for ConflictDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} already exists for {} ({}): ",
+            self.destination.display(),
+            self.source.display(),
+            self.policy
+        )?;
+        match &self.outcome {
+            ConflictOutcome::Skipped => f.write_str("skipped"),
+            ConflictOutcome::Overwritten => f.write_str("overwriting"),
+            ConflictOutcome::Renamed(path) => write!(f, "renamed to {}", path.display()),
+            ConflictOutcome::Duplicate => f.write_str("identical, skipped"),
+        }
+    }
+}
+
+/// What the planner should do with a file after checking for conflicts.
+pub(crate) enum Resolution {
+    /// Transfer the file to `destination`, replacing any existing file if `overwrite` is set.
+    Transfer { destination: PathBuf, overwrite: bool },
+    /// Leave the file where it is.
+    Skip(SkipReason),
+}
+
+/// Decides where `source` should go given that it is destined for `destination`.
+///
+/// `is_planned` reports whether another file in the same run is already headed for
+/// a path. Such collisions are never resolved by overwriting, since that would
+/// discard a file that is part of the run; they are skipped under the `Skip`
+/// policy and renamed with a numeric suffix otherwise.
+///
+/// Renamed files keep any of the `compound_extensions` intact, e.g.
+/// `backup (1).tar.gz`.
+pub(crate) fn resolve(
+    source: &Path,
+    destination: PathBuf,
+    policy: ConflictPolicy,
+    compound_extensions: &[String],
+    is_planned: impl Fn(&Path) -> bool,
+) -> io::Result<(Resolution, Option<ConflictDecision>)> {
+    // A broken link still takes up its name.
+    let taken = |path: &Path| path.symlink_metadata().is_ok() || is_planned(path);
+
+    if !taken(&destination) {
+        return Ok((Resolution::Transfer { destination, overwrite: false }, None));
+    }
+
+    let (resolution, outcome) = if is_planned(&destination) {
+        // Another file in this run is headed for the same path.
+        match policy {
+            ConflictPolicy::Skip => (Resolution::Skip(SkipReason::DestinationExists), ConflictOutcome::Skipped),
+            _ => {
+                let renamed = numbered_name(&destination, compound_extensions, &taken);
+                (renamed_to(&renamed), ConflictOutcome::Renamed(renamed))
+            }
+        }
+    } else {
+        match policy {
+            ConflictPolicy::Skip => (Resolution::Skip(SkipReason::DestinationExists), ConflictOutcome::Skipped),
+            ConflictPolicy::Overwrite => (overwrite(&destination), ConflictOutcome::Overwritten),
+            ConflictPolicy::RenameNumbered => {
+                let renamed = numbered_name(&destination, compound_extensions, &taken);
+                (renamed_to(&renamed), ConflictOutcome::Renamed(renamed))
+            }
+            ConflictPolicy::RenameTimestamp => {
+                let renamed = timestamped_name(source, &destination, compound_extensions, &taken)?;
+                (renamed_to(&renamed), ConflictOutcome::Renamed(renamed))
+            }
+            ConflictPolicy::KeepNewer => {
+                if source.metadata()?.modified()? > destination.metadata()?.modified()? {
+                    (overwrite(&destination), ConflictOutcome::Overwritten)
+                } else {
+                    (Resolution::Skip(SkipReason::ExistingIsNewer), ConflictOutcome::Skipped)
+                }
+            }
+            ConflictPolicy::KeepLarger => {
+                if source.metadata()?.len() > destination.metadata()?.len() {
+                    (overwrite(&destination), ConflictOutcome::Overwritten)
+                } else {
+                    (Resolution::Skip(SkipReason::ExistingIsLarger), ConflictOutcome::Skipped)
+                }
+            }
+            ConflictPolicy::Dedupe => {
+                if same_content(source, &destination)? {
+                    (Resolution::Skip(SkipReason::Duplicate), ConflictOutcome::Duplicate)
+                } else {
+                    let renamed = numbered_name(&destination, compound_extensions, &taken);
+                    (renamed_to(&renamed), ConflictOutcome::Renamed(renamed))
+                }
+            }
+        }
+    };
+
+    let decision = ConflictDecision {
+        source: source.to_path_buf(),
+        destination,
+        policy,
+        outcome,
+    };
+    Ok((resolution, Some(decision)))
+}
+
+fn overwrite(destination: &Path) -> Resolution {
+    Resolution::Transfer { destination: destination.to_path_buf(), overwrite: true }
+}
+
+fn renamed_to(destination: &Path) -> Resolution {
+    Resolution::Transfer { destination: destination.to_path_buf(), overwrite: false }
+}
+
+/// Builds a sibling of `path` whose file stem has `suffix` appended.
+fn with_stem_suffix(path: &Path, suffix: &str, compound_extensions: &[String]) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let file_name = match split_extension(&file_name, compound_extensions) {
+        (stem, Some(extension)) => format!("{}{}.{}", stem, suffix, extension),
+        (stem, None) => format!("{}{}", stem, suffix),
+    };
+    path.with_file_name(file_name)
+}
+
+/// Finds the first free name of the form `stem (n).ext`.
+fn numbered_name(path: &Path, compound_extensions: &[String], taken: &impl Fn(&Path) -> bool) -> PathBuf {
+    (1..)
+        .map(|n| with_stem_suffix(path, &format!(" ({})", n), compound_extensions))
+        .find(|candidate| !taken(candidate))
+        .unwrap()
+}
+
+/// Finds a free name suffixed with the modification time of `source`.
+fn timestamped_name(
+    source: &Path,
+    path: &Path,
+    compound_extensions: &[String],
+    taken: &impl Fn(&Path) -> bool,
+) -> io::Result<PathBuf> {
+    let modified: DateTime<Local> = source.metadata()?.modified()?.into();
+    let candidate = with_stem_suffix(path, &modified.format("_%Y%m%d-%H%M%S").to_string(), compound_extensions);
+    if taken(&candidate) {
+        Ok(numbered_name(&candidate, compound_extensions, taken))
+    } else {
+        Ok(candidate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::default_compound_extensions;
+
+    #[test]
+    fn appends_the_suffix_to_the_stem() {
+        let compound = default_compound_extensions();
+        let suffix = |path: &str| with_stem_suffix(Path::new(path), "_1", &compound);
+        assert_eq!(suffix("Images/photo.jpg"), Path::new("Images/photo_1.jpg"));
+        assert_eq!(suffix("Archives/backup.tar.gz"), Path::new("Archives/backup_1.tar.gz"));
+        assert_eq!(suffix("Others/README"), Path::new("Others/README_1"));
+        assert_eq!(suffix("Others/.bashrc"), Path::new("Others/.bashrc_1"));
+    }
+
+    #[test]
+    fn numbers_the_first_free_name() {
+        let compound = default_compound_extensions();
+        let taken = [PathBuf::from("a/report (1).pdf"), PathBuf::from("a/report (2).pdf")];
+        let taken = |path: &Path| taken.iter().any(|taken| taken == path);
+        assert_eq!(
+            numbered_name(Path::new("a/report.pdf"), &compound, &taken),
+            Path::new("a/report (3).pdf")
+        );
+        assert_eq!(
+            numbered_name(Path::new("a/notes.txt"), &compound, &taken),
+            Path::new("a/notes (1).txt")
+        );
+        assert_eq!(
+            numbered_name(Path::new("a/site.tar.gz"), &compound, &|_: &Path| false),
+            Path::new("a/site (1).tar.gz")
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Date-based organization into folders such as `2024/03`.
+//!
+//! When `Config::date_layout` is set, files are filed by a date taken from their
+//! metadata or their name, rendered through a [`Template`] such as
+//! `{year}/{month:02}`, optionally beneath their category folder.
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::template::Template;
+
+/// Where the date used to organize a file comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateSource {
+    /// The time the file was last modified.
+    #[default]
+    Modified,
+    /// The time the file was created, where the file system records it,
+    /// otherwise the modification time.
+    Created,
+    /// A date in the file name such as `IMG_20240315_101500.jpg` or
+    /// `2024-03-15 report.pdf`, otherwise the modification time.
+    Filename,
+}
+
+impl DateSource {
+    /// Every source, in the order they are offered to users.
+    pub const ALL: [DateSource; 3] = [DateSource::Modified, DateSource::Created, DateSource::Filename];
+
+    /// The name used for the source in configuration files and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DateSource::Modified => "modified",
+            DateSource::Created => "created",
+            DateSource::Filename => "filename",
+        }
+    }
+
+    /// A short human-readable description of the source.
+    pub fn label(&self) -> &'static str {
+        match self {
+            DateSource::Modified => "Modification time",
+            DateSource::Created => "Creation time",
+            DateSource::Filename => "Date in file name",
+        }
+    }
+}
+
+impl FromStr for DateSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DateSource::ALL
+            .into_iter()
+            .find(|source| source.name() == s)
+            .ok_or_else(|| format!("unknown date source '{}', expected one of: modified, created, filename", s))
+    }
+}
+
+/// How files are organized by date.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DateLayout {
+    /// Where each file's date comes from.
+    pub source: DateSource,
+    /// The folder layout, usually built from the `{year}`, `{month}` and `{day}`
+    /// placeholders, though any [`Template`] placeholder may be used.
+    pub pattern: Template,
+    /// If true, the date folders are created inside each file's category folder,
+    /// e.g. `Images/2024/03`; otherwise directly in the organized directory.
+    pub with_category: bool,
+}
+
+impl Default for DateLayout {
+    fn default() -> Self {
+        Self {
+            source: DateSource::default(),
+            pattern: "{year}/{month:02}".parse().expect("default pattern is valid"),
+            with_category: false,
+        }
+    }
+}
+
+/// Determines the date used to organize the file at `path`.
+pub(crate) fn file_date(path: &Path, source: DateSource) -> io::Result<NaiveDate> {
+    let metadata = fs::metadata(path)?;
+
+    if source == DateSource::Filename {
+        if let Some(date) = path.file_name().and_then(|name| date_in_name(&name.to_string_lossy())) {
+            return Ok(date);
+        }
+    }
+
+    let time = match source {
+        DateSource::Created => metadata.created().or_else(|_| metadata.modified())?,
+        DateSource::Modified | DateSource::Filename => metadata.modified()?,
+    };
+    Ok(DateTime::<Local>::from(time).date_naive())
+}
+
+/// Finds the first plausible `YYYY?MM?DD` date in a file name, where `?` is an
+/// optional `-`, `_`, `.` or space separator.
+fn date_in_name(name: &str) -> Option<NaiveDate> {
+    let bytes = name.as_bytes();
+
+    (0..bytes.len()).find_map(|start| {
+        // A date must not be the tail of a longer number.
+        if start > 0 && bytes[start - 1].is_ascii_digit() {
+            return None;
+        }
+
+        let mut fields = [0u32; 3];
+        let mut position = start;
+        for (i, (field, len)) in fields.iter_mut().zip([4, 2, 2]).enumerate() {
+            if i > 0 && matches!(bytes.get(position), Some(b'-' | b'_' | b'.' | b' ')) {
+                position += 1;
+            }
+            let digits = bytes.get(position..position + len)?;
+            if !digits.iter().all(u8::is_ascii_digit) {
+                return None;
+            }
+            *field = std::str::from_utf8(digits).ok()?.parse().ok()?;
+            position += len;
+        }
+
+        if bytes.get(position).is_some_and(u8::is_ascii_digit) && position - start == 8 {
+            // Part of a longer number such as a 10-digit id.
+            return None;
+        }
+
+        let [year, month, day] = fields;
+        if !(1900..=2100).contains(&year) {
+            return None;
+        }
+        NaiveDate::from_ymd_opt(year as i32, month, day)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(year, month, day)
+    }
+
+    #[test]
+    fn finds_dates_with_and_without_separators() {
+        assert_eq!(date_in_name("IMG_20240307_123456.jpg"), date(2024, 3, 7));
+        assert_eq!(date_in_name("Scan 2023-12-31.pdf"), date(2023, 12, 31));
+        assert_eq!(date_in_name("2021.01.02 notes.txt"), date(2021, 1, 2));
+        assert_eq!(date_in_name("report_2020_02_29.docx"), date(2020, 2, 29));
+        assert_eq!(date_in_name("2019 06 15.png"), date(2019, 6, 15));
+    }
+
+    #[test]
+    fn takes_the_first_plausible_date() {
+        assert_eq!(date_in_name("2024-13-01 then 2024-02-03.txt"), date(2024, 2, 3));
+        assert_eq!(date_in_name("2001-01-01 and 2002-02-02"), date(2001, 1, 1));
+    }
+
+    #[test]
+    fn ignores_implausible_dates() {
+        assert_eq!(date_in_name("notes.txt"), None);
+        assert_eq!(date_in_name("2023-02-29.txt"), None);
+        assert_eq!(date_in_name("1850-01-01.txt"), None);
+        assert_eq!(date_in_name("2024-1-5.txt"), None);
+        assert_eq!(date_in_name("2024--03-07.txt"), None);
+    }
+
+    #[test]
+    fn ignores_dates_inside_longer_numbers() {
+        assert_eq!(date_in_name("order 120240307.pdf"), None);
+        assert_eq!(date_in_name("id 2024030712.pdf"), None);
+    }
+}
@@ -0,0 +1,191 @@
+//! Finding files with identical content.
+//!
+//! Files are first grouped by size, and only files sharing a size are hashed,
+//! so most files are never read. Within each group of identical files one is
+//! kept as the original, and [`DuplicatePolicy`] decides what happens to the
+//! rest.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::cancel::CancellationToken;
+use crate::digest::file_digest;
+
+/// What to do with files whose content is identical to another file being organized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    /// Duplicates are not looked for.
+    #[default]
+    Ignore,
+    /// Duplicates are organized like any other file and listed in the report.
+    Report,
+    /// One copy is organized and the others are moved into the duplicates folder.
+    Move,
+    /// Every copy is organized, then the others are replaced with hard links to
+    /// the one kept, so the content is stored only once.
+    HardLink,
+}
+
+impl DuplicatePolicy {
+    /// Every policy, in the order they are offered to users.
+    pub const ALL: [DuplicatePolicy; 4] = [
+        DuplicatePolicy::Ignore,
+        DuplicatePolicy::Report,
+        DuplicatePolicy::Move,
+        DuplicatePolicy::HardLink,
+    ];
+
+    /// The name used for the policy in configuration files and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DuplicatePolicy::Ignore => "ignore",
+            DuplicatePolicy::Report => "report",
+            DuplicatePolicy::Move => "move",
+            DuplicatePolicy::HardLink => "hard_link",
+        }
+    }
+
+    /// A short human-readable description of the policy.
+    pub fn label(&self) -> &'static str {
+        match self {
+            DuplicatePolicy::Ignore => "Don't look for duplicates",
+            DuplicatePolicy::Report => "Report duplicates",
+            DuplicatePolicy::Move => "Move duplicates into their own folder",
+            DuplicatePolicy::HardLink => "Replace duplicates with hard links",
+        }
+    }
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DuplicatePolicy::ALL
+            .into_iter()
+            .find(|policy| policy.name() == s)
+            .ok_or_else(|| format!("unknown duplicate policy '{}', expected one of: ignore, report, move, hard_link", s))
+    }
+}
+
+/// A set of files with identical content.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DuplicateGroup {
+    /// The size of each file in bytes.
+    pub size: u64,
+    /// The copy that is kept: the one with the shortest name, so that
+    /// `report.pdf` wins over `report (1).pdf`.
+    pub original: PathBuf,
+    /// The other copies.
+    pub duplicates: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    /// Bytes taken up by the duplicates, which keeping one copy would free.
+    pub fn wasted_bytes(&self) -> u64 {
+        self.size * self.duplicates.len() as u64
+    }
+}
+
+/// Groups `files` by identical content, returning only groups with more than one file.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if a file cannot be read, or an `Interrupted`
+/// error if `cancel` is cancelled.
+pub(crate) fn find_duplicates(files: &[PathBuf], cancel: &CancellationToken) -> io::Result<Vec<DuplicateGroup>> {
+    let mut by_size: HashMap<u64, Vec<&PathBuf>> = HashMap::new();
+    for file in files {
+        by_size.entry(fs::metadata(file)?.len()).or_default().push(file);
+    }
+
+    let mut groups = Vec::new();
+    for (size, candidates) in by_size {
+        // Empty files are all alike, but there is nothing to gain from them.
+        if candidates.len() < 2 || size == 0 {
+            continue;
+        }
+
+        let mut by_digest: HashMap<[u8; 32], Vec<PathBuf>> = HashMap::new();
+        for file in candidates {
+            if cancel.is_cancelled() {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "operation cancelled"));
+            }
+            by_digest.entry(file_digest(file)?).or_default().push(file.clone());
+        }
+
+        for mut copies in by_digest.into_values().filter(|copies| copies.len() > 1) {
+            copies.sort_by_key(|path| (path.file_name().map_or(0, |name| name.len()), path.clone()));
+            let original = copies.remove(0);
+            groups.push(DuplicateGroup { size, original, duplicates: copies });
+        }
+    }
+
+    groups.sort_by(|a, b| a.original.cmp(&b.original));
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn write(directory: &Path, files: &[(&str, &str)]) -> Vec<PathBuf> {
+        files
+            .iter()
+            .map(|(name, content)| {
+                let path = directory.join(name);
+                fs::write(&path, content).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    #[test]
+    fn groups_identical_files_keeping_the_shortest_name() {
+        let scratch = tempfile::tempdir().unwrap();
+        let files = write(
+            scratch.path(),
+            &[
+                ("report (1).pdf", "report"),
+                ("report.pdf", "report"),
+                ("report (2).pdf", "report"),
+                ("other.pdf", "others"),
+                ("notes.txt", "notes"),
+            ],
+        );
+
+        let groups = find_duplicates(&files, &CancellationToken::new()).unwrap();
+        let dir = scratch.path();
+        assert_eq!(
+            groups,
+            [DuplicateGroup {
+                size: 6,
+                original: dir.join("report.pdf"),
+                duplicates: vec![dir.join("report (1).pdf"), dir.join("report (2).pdf")],
+            }]
+        );
+        assert_eq!(groups[0].wasted_bytes(), 12);
+    }
+
+    #[test]
+    fn ignores_empty_files() {
+        let scratch = tempfile::tempdir().unwrap();
+        let files = write(scratch.path(), &[("a", ""), ("b", "")]);
+        assert!(find_duplicates(&files, &CancellationToken::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let scratch = tempfile::tempdir().unwrap();
+        let files = write(scratch.path(), &[("a", "same"), ("b", "same")]);
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let err = find_duplicates(&files, &cancel).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    }
+}
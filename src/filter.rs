@@ -0,0 +1,315 @@
+//! Include and exclude patterns deciding which files are organized.
+//!
+//! `Config::exclude` and `Config::include` hold glob patterns such as `*.tmp`,
+//! `desktop.ini` or `important-*`. A pattern without a `/` is matched against
+//! the name of each file or directory, and one with a `/` against its path
+//! relative to the organized directory. In addition, an [`IGNORE_FILE`] in the
+//! organized directory lists patterns in gitignore syntax.
+//!
+//! Files can also be filtered by size and by age, e.g. to sweep only files that
+//! have not been touched for a week.
+
+use globset::{Glob, GlobBuilder, GlobMatcher};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use crate::plan::SkipReason;
+use crate::Config;
+
+/// Name of the file listing paths to leave alone, in gitignore syntax.
+pub const IGNORE_FILE: &str = ".fileorgignore";
+
+/// Which timestamp a file's age is measured from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgeSource {
+    /// The time the file was last modified.
+    #[default]
+    Modified,
+    /// The time the file was last read, where the file system records it.
+    Accessed,
+}
+
+impl AgeSource {
+    /// Every source, in the order they are offered to users.
+    pub const ALL: [AgeSource; 2] = [AgeSource::Modified, AgeSource::Accessed];
+
+    /// The name used for the source in configuration files and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AgeSource::Modified => "modified",
+            AgeSource::Accessed => "accessed",
+        }
+    }
+
+    /// A short human-readable description of the source.
+    pub fn label(&self) -> &'static str {
+        match self {
+            AgeSource::Modified => "Last modified",
+            AgeSource::Accessed => "Last accessed",
+        }
+    }
+}
+
+impl FromStr for AgeSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AgeSource::ALL
+            .into_iter()
+            .find(|source| source.name() == s)
+            .ok_or_else(|| format!("unknown age source '{}', expected one of: modified, accessed", s))
+    }
+}
+
+/// Length of a day, the unit of the age limits.
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A glob pattern together with its original text, for reporting matches.
+struct Pattern {
+    text: String,
+    matcher: GlobMatcher,
+}
+
+impl Pattern {
+    fn matches(&self, path: &Path, relative: &Path) -> bool {
+        if self.text.contains('/') {
+            self.matcher.is_match(relative)
+        } else {
+            path.file_name().is_some_and(|name| self.matcher.is_match(name))
+        }
+    }
+}
+
+/// Compiles a pattern from `Config::include` or `Config::exclude`.
+pub(crate) fn compile(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern.trim_start_matches('/')).literal_separator(true).build()
+}
+
+/// The include and exclude rules in effect for one organized directory.
+pub(crate) struct Filter {
+    root: PathBuf,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    ignore_file: Option<Gitignore>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    min_age: Option<Duration>,
+    max_age: Option<Duration>,
+    age_source: AgeSource,
+}
+
+impl Filter {
+    /// Builds the filter for organizing `root`, reading its ignore file if there is one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `std::io::Error` if a pattern is invalid or the ignore file
+    /// cannot be read.
+    pub(crate) fn new(root: &Path, config: &Config) -> io::Result<Self> {
+        let patterns = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|text| {
+                    let matcher = compile(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                    Ok(Pattern { text: text.clone(), matcher: matcher.compile_matcher() })
+                })
+                .collect::<io::Result<Vec<_>>>()
+        };
+
+        let path = root.join(IGNORE_FILE);
+        let ignore_file = if path.is_file() {
+            let mut builder = GitignoreBuilder::new(root);
+            if let Some(err) = builder.add(&path) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+            }
+            Some(builder.build().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            include: patterns(&config.include)?,
+            exclude: patterns(&config.exclude)?,
+            ignore_file,
+            min_size: config.min_size,
+            max_size: config.max_size,
+            min_age: config.min_age_days.map(|days| DAY * days),
+            max_age: config.max_age_days.map(|days| DAY * days),
+            age_source: config.age_source,
+        })
+    }
+
+    /// Decides whether the item at `path` is left alone, and why.
+    ///
+    /// Exclude patterns and the ignore file apply to files and directories;
+    /// include patterns only to files.
+    pub(crate) fn check(&self, path: &Path, is_dir: bool) -> Option<SkipReason> {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+
+        if let Some(pattern) = self.exclude.iter().find(|pattern| pattern.matches(path, relative)) {
+            return Some(SkipReason::Excluded(pattern.text.clone()));
+        }
+        if let Some(ignore_file) = &self.ignore_file {
+            if let Some(glob) = ignore_file.matched(relative, is_dir).inner().filter(|glob| !glob.is_whitelist()) {
+                return Some(SkipReason::Excluded(glob.original().to_owned()));
+            }
+        }
+        if !is_dir && !self.include.is_empty() && !self.include.iter().any(|pattern| pattern.matches(path, relative)) {
+            return Some(SkipReason::NotIncluded);
+        }
+        None
+    }
+
+    /// Decides whether the file at `path` is left alone because of its size or age.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `std::io::Error` if a limit is set and the file's metadata
+    /// cannot be read.
+    pub(crate) fn check_size_and_age(&self, path: &Path) -> io::Result<Option<SkipReason>> {
+        if self.min_size.is_none() && self.max_size.is_none() && self.min_age.is_none() && self.max_age.is_none() {
+            return Ok(None);
+        }
+
+        let metadata = fs::metadata(path)?;
+        let size = metadata.len();
+        if self.min_size.is_some_and(|min| size < min) {
+            return Ok(Some(SkipReason::TooSmall));
+        }
+        if self.max_size.is_some_and(|max| size > max) {
+            return Ok(Some(SkipReason::TooLarge));
+        }
+
+        if self.min_age.is_some() || self.max_age.is_some() {
+            let time = match self.age_source {
+                AgeSource::Modified => metadata.modified()?,
+                AgeSource::Accessed => metadata.accessed()?,
+            };
+            // Timestamps in the future count as brand new.
+            let age = SystemTime::now().duration_since(time).unwrap_or_default();
+            if self.min_age.is_some_and(|min| age < min) {
+                return Ok(Some(SkipReason::TooNew));
+            }
+            if self.max_age.is_some_and(|max| age > max) {
+                return Ok(Some(SkipReason::TooOld));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(root: &Path, include: &[&str], exclude: &[&str]) -> Filter {
+        let strings = |patterns: &[&str]| patterns.iter().map(|pattern| pattern.to_string()).collect();
+        let config = Config { include: strings(include), exclude: strings(exclude), ..Config::default() };
+        Filter::new(root, &config).unwrap()
+    }
+
+    #[test]
+    fn excludes_by_name_or_relative_path() {
+        let root = Path::new("/inbox");
+        let filter = filter(root, &[], &["*.tmp", "desktop.ini", "drafts/*.txt"]);
+        assert_eq!(filter.check(&root.join("a/b.tmp"), false), Some(SkipReason::Excluded("*.tmp".to_owned())));
+        assert_eq!(filter.check(&root.join("desktop.ini"), true), Some(SkipReason::Excluded("desktop.ini".to_owned())));
+        assert_eq!(
+            filter.check(&root.join("drafts/notes.txt"), false),
+            Some(SkipReason::Excluded("drafts/*.txt".to_owned()))
+        );
+        // A pattern with a separator matches whole components only.
+        assert_eq!(filter.check(&root.join("drafts/old/notes.txt"), false), None);
+        assert_eq!(filter.check(&root.join("notes.txt"), false), None);
+    }
+
+    #[test]
+    fn includes_only_matching_files() {
+        let root = Path::new("/inbox");
+        let filter = filter(root, &["*.pdf", "scans/*"], &["secret-*"]);
+        assert_eq!(filter.check(&root.join("report.pdf"), false), None);
+        assert_eq!(filter.check(&root.join("scans/page.png"), false), None);
+        assert_eq!(filter.check(&root.join("photo.jpg"), false), Some(SkipReason::NotIncluded));
+        // Directories are still walked, and excludes win over includes.
+        assert_eq!(filter.check(&root.join("photos"), true), None);
+        assert_eq!(
+            filter.check(&root.join("secret-report.pdf"), false),
+            Some(SkipReason::Excluded("secret-*".to_owned()))
+        );
+    }
+
+    #[test]
+    fn reads_the_ignore_file() {
+        let scratch = tempfile::tempdir().unwrap();
+        let root = scratch.path();
+        fs::write(root.join(IGNORE_FILE), "# Work in progress\n*.part\nkeep/\n!important.part\n").unwrap();
+        let filter = filter(root, &[], &[]);
+
+        assert_eq!(filter.check(&root.join("film.part"), false), Some(SkipReason::Excluded("*.part".to_owned())));
+        assert_eq!(filter.check(&root.join("keep"), true), Some(SkipReason::Excluded("keep/".to_owned())));
+        assert_eq!(filter.check(&root.join("keep"), false), None);
+        assert_eq!(filter.check(&root.join("important.part"), false), None);
+        assert_eq!(filter.check(&root.join("film.mkv"), false), None);
+    }
+
+    #[test]
+    fn filters_by_size() {
+        let scratch = tempfile::tempdir().unwrap();
+        let root = scratch.path();
+        for (name, size) in [("small", 10), ("medium", 100), ("large", 1000)] {
+            fs::write(root.join(name), vec![0; size]).unwrap();
+        }
+        let config = Config { min_size: Some(100), max_size: Some(999), ..Config::default() };
+        let filter = Filter::new(root, &config).unwrap();
+
+        assert_eq!(filter.check_size_and_age(&root.join("small")).unwrap(), Some(SkipReason::TooSmall));
+        assert_eq!(filter.check_size_and_age(&root.join("medium")).unwrap(), None);
+        assert_eq!(filter.check_size_and_age(&root.join("large")).unwrap(), Some(SkipReason::TooLarge));
+    }
+
+    #[test]
+    fn filters_by_age() {
+        let scratch = tempfile::tempdir().unwrap();
+        let root = scratch.path();
+        let now = SystemTime::now();
+        for (name, days_old) in [("new", 0), ("week", 7), ("year", 365)] {
+            let file = fs::File::create(root.join(name)).unwrap();
+            file.set_modified(now - DAY * days_old - Duration::from_secs(60)).unwrap();
+        }
+        let config = Config { min_age_days: Some(3), max_age_days: Some(30), ..Config::default() };
+        let filter = Filter::new(root, &config).unwrap();
+
+        assert_eq!(filter.check_size_and_age(&root.join("new")).unwrap(), Some(SkipReason::TooNew));
+        assert_eq!(filter.check_size_and_age(&root.join("week")).unwrap(), None);
+        assert_eq!(filter.check_size_and_age(&root.join("year")).unwrap(), Some(SkipReason::TooOld));
+        assert!(SkipReason::TooOld.is_size_or_age());
+    }
+
+    #[test]
+    fn needs_no_metadata_without_limits() {
+        let filter = Filter::new(Path::new("/inbox"), &Config::default()).unwrap();
+        assert_eq!(filter.check_size_and_age(Path::new("/inbox/missing")).unwrap(), None);
+    }
+
+    #[test]
+    fn parses_age_sources() {
+        for source in AgeSource::ALL {
+            assert_eq!(source.name().parse::<AgeSource>(), Ok(source));
+        }
+        assert!("created".parse::<AgeSource>().is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let config = Config { exclude: vec!["[unclosed".to_owned()], ..Config::default() };
+        let err = Filter::new(Path::new("/inbox"), &config).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -1,10 +1,21 @@
 use eframe::{egui, App};
 use egui::{Color32, RichText, Stroke, Vec2, IconData, FontId, FontFamily};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
-use crate::Config;
-use crate::organise_files;
+use crate::{
+    default_categories, default_sidecar_rules, CategoryRule, Config, ConfigError, ConflictPolicy, ContentDetection,
+    DateLayout, DateSource, DuplicatePolicy, ProjectPolicy, RecursiveLayout, SidecarRule, SymlinkPolicy, Template,
+};
+use crate::plan::plan_with;
+use crate::report::format_bytes;
+use crate::{
+    apply_plan_with, undo_run, CancellationToken, ChannelProgress, FailedItem, Plan,
+    ProgressEvent, RunReport,
+};
 
 /// Background color used in the default dark theme.
 const BACKGROUND_COLOR: Color32 = Color32::from_rgb(16, 24, 38); // Dark navy blue
@@ -15,23 +26,73 @@ const TEXT_COLOR: Color32 = Color32::from_rgb(226, 232, 240);    // Light gray
 /// A darker variant of the accent color.
 const DARKER_ACCENT: Color32 = Color32::from_rgb(30, 58, 138);   // Darker blue
 
+/// What a finished background operation accomplished.
+enum Outcome {
+    /// Files were organized; the report holds the run id and any failures.
+    Organized(Box<RunReport>),
+    /// The run with the given id was undone.
+    Undone(String),
+    /// Nothing was changed because the plan is large enough to need reviewing first.
+    NeedsConfirmation(Box<Plan>),
+    /// A plan was made to be previewed; nothing was changed.
+    Planned(Box<Plan>),
+}
+
 enum ProcessState {
     Idle,
-    Running { progress: f32, message: String },
-    Complete { success: bool, message: String },
+    Running { progress: f32, message: String, cancellable: bool },
+    Complete { success: bool, message: String, failures: Vec<FailedItem> },
 }
 
 pub struct FileOrganizerApp {
+    /// The loaded settings, including those the form does not expose.
+    settings: Config,
     directory: String,
+    /// Where organized folders are created; empty means inside `directory`.
+    destination_root: String,
     log_file: String,
     others_directory: String,
     copy_files: bool,
+    verify_transfers: bool,
+    continue_on_error: bool,
     include_hidden: bool,
+    allow_project_root: bool,
+    /// Whether only files older than `min_age_days` are organized.
+    limit_age: bool,
+    min_age_days: u32,
+    recursive: bool,
+    limit_depth: bool,
+    max_depth: usize,
+    recursive_layout: RecursiveLayout,
+    project_policy: ProjectPolicy,
+    symlink_policy: SymlinkPolicy,
+    conflict_policy: ConflictPolicy,
+    duplicate_policy: DuplicatePolicy,
+    content_detection: ContentDetection,
+    use_template: bool,
+    destination_template: String,
+    organize_by_date: bool,
+    date_source: DateSource,
+    date_pattern: String,
+    date_with_category: bool,
+    use_categories: bool,
+    categories: Vec<CategoryRule>,
+    use_sidecars: bool,
+    sidecars: Vec<SidecarRule>,
     directory_browse_dialog_open: bool,
+    destination_browse_dialog_open: bool,
     log_file_browse_dialog_open: bool,
     theme_mode: ThemeMode,
     process_state: ProcessState,
-    operation_result: Arc<Mutex<Option<Result<(), String>>>>,
+    operation_result: Arc<Mutex<Option<Result<Outcome, String>>>>,
+    /// Progress notifications from the running operation, if any.
+    progress_events: Option<Receiver<ProgressEvent>>,
+    progress_total: usize,
+    progress_done: usize,
+    /// Cancels the running operation.
+    cancel: CancellationToken,
+    pending_plan: Option<Plan>,
+    last_run_id: Option<String>,
 }
 
 enum ThemeMode {
@@ -42,19 +103,202 @@ enum ThemeMode {
 
 impl Default for FileOrganizerApp {
     fn default() -> Self {
+        Self::from_config(&Config::default())
+    }
+}
+
+impl FileOrganizerApp {
+    /// Creates the app with its form filled in from `config`.
+    fn from_config(config: &Config) -> Self {
+        let date_layout = config.date_layout.clone().unwrap_or_default();
         Self {
+            settings: config.clone(),
             directory: String::new(),
-            log_file: "file_organizer.log".to_owned(),
-            others_directory: "Others".to_owned(),
-            copy_files: false,
-            include_hidden: false,
+            destination_root: config.destination_root.clone().unwrap_or_default(),
+            log_file: config.log_file.clone(),
+            others_directory: config.others_directory.clone(),
+            copy_files: config.copy,
+            verify_transfers: config.verify_transfers,
+            continue_on_error: config.continue_on_error,
+            include_hidden: config.include_hidden,
+            allow_project_root: config.allow_project_root,
+            limit_age: config.min_age_days.is_some(),
+            min_age_days: config.min_age_days.unwrap_or(7),
+            recursive: config.recursive,
+            limit_depth: config.max_depth.is_some(),
+            max_depth: config.max_depth.unwrap_or(1),
+            recursive_layout: config.recursive_layout,
+            project_policy: config.project_policy,
+            symlink_policy: config.symlink_policy,
+            conflict_policy: config.conflict_policy,
+            duplicate_policy: config.duplicate_policy,
+            content_detection: config.content_detection,
+            use_template: config.destination_template.is_some(),
+            destination_template: config
+                .destination_template
+                .as_ref()
+                .map_or_else(|| "{category}/{year}".to_owned(), ToString::to_string),
+            organize_by_date: config.date_layout.is_some(),
+            date_source: date_layout.source,
+            date_pattern: date_layout.pattern.to_string(),
+            date_with_category: date_layout.with_category,
+            use_categories: !config.categories.is_empty(),
+            categories: if config.categories.is_empty() {
+                default_categories()
+            } else {
+                config.categories.clone()
+            },
+            use_sidecars: !config.sidecars.is_empty(),
+            sidecars: if config.sidecars.is_empty() {
+                default_sidecar_rules()
+            } else {
+                config.sidecars.clone()
+            },
             directory_browse_dialog_open: false,
+            destination_browse_dialog_open: false,
             log_file_browse_dialog_open: false,
             theme_mode: ThemeMode::Dark,
             process_state: ProcessState::Idle,
             operation_result: Arc::new(Mutex::new(None)),
+            progress_events: None,
+            progress_total: 0,
+            progress_done: 0,
+            cancel: CancellationToken::new(),
+            pending_plan: None,
+            last_run_id: None,
+        }
+    }
+
+    /// Creates the app from the user configuration file, falling back to the
+    /// defaults (and reporting the problem) if it cannot be loaded.
+    fn new() -> Self {
+        match Config::discover(None, None) {
+            Ok(config) => Self::from_config(&config),
+            Err(err) => Self {
+                process_state: ProcessState::Complete {
+                    success: false,
+                    message: format!("Could not load settings: {}", err),
+                    failures: Vec::new(),
+                },
+                ..Self::default()
+            },
         }
     }
+
+    /// Saves the current form settings to the user configuration file.
+    fn save_settings(&mut self) {
+        let result = Config::user_config_path()
+            .ok_or_else(|| "no configuration directory is available".to_owned())
+            .and_then(|path| {
+                let config = self.config();
+                config.validate().map_err(|e| e.to_string())?;
+                config.save(&path).map_err(|e| e.to_string())?;
+                Ok(path)
+            });
+
+        self.process_state = match result {
+            Ok(path) => ProcessState::Complete {
+                success: true,
+                message: format!("Settings saved to {}", path.display()),
+                failures: Vec::new(),
+            },
+            Err(err) => ProcessState::Complete {
+                success: false,
+                message: format!("Could not save settings: {}", err),
+                failures: Vec::new(),
+            },
+        };
+    }
+
+    /// Builds a `Config` from the current state of the form, keeping any
+    /// loaded settings the form does not expose.
+    fn config(&self) -> Config {
+        Config {
+            copy: self.copy_files,
+            verify_transfers: self.verify_transfers,
+            continue_on_error: self.continue_on_error,
+            include_hidden: self.include_hidden,
+            allow_project_root: self.allow_project_root,
+            min_age_days: self.limit_age.then_some(self.min_age_days),
+            recursive: self.recursive,
+            max_depth: self.limit_depth.then_some(self.max_depth),
+            recursive_layout: self.recursive_layout,
+            project_policy: self.project_policy,
+            symlink_policy: self.symlink_policy,
+            destination_root: Some(self.destination_root.trim())
+                .filter(|root| !root.is_empty())
+                .map(str::to_owned),
+            others_directory: self.others_directory.clone(),
+            log_file: self.log_file.clone(),
+            conflict_policy: self.conflict_policy,
+            duplicate_policy: self.duplicate_policy,
+            content_detection: self.content_detection,
+            destination_template: self.destination_template().ok().filter(|_| self.use_template),
+            date_layout: self.organize_by_date.then(|| DateLayout {
+                source: self.date_source,
+                pattern: self.date_pattern().unwrap_or_else(|_| DateLayout::default().pattern),
+                with_category: self.date_with_category,
+            }),
+            categories: if self.use_categories {
+                self.categories.clone()
+            } else {
+                Vec::new()
+            },
+            sidecars: if self.use_sidecars {
+                self.sidecars.clone()
+            } else {
+                Vec::new()
+            },
+            ..self.settings.clone()
+        }
+    }
+
+    /// Builds the configuration for organizing the chosen directory: the user
+    /// configuration and the directory's own `.fileorg.toml`, with the changes
+    /// made in the form on top.
+    fn directory_config(&self) -> Result<Config, ConfigError> {
+        let loaded = Self::from_config(&self.settings).config();
+        Config::discover(Some(Path::new(&self.directory)), None)?.with_changes(&loaded, &self.config())
+    }
+
+    /// Parses the date pattern typed into the form.
+    fn date_pattern(&self) -> Result<Template, String> {
+        self.date_pattern.parse().map_err(|e: crate::TemplateError| e.to_string())
+    }
+
+    /// Parses the destination template typed into the form.
+    fn destination_template(&self) -> Result<Template, String> {
+        self.destination_template.parse().map_err(|e: crate::TemplateError| e.to_string())
+    }
+
+    /// Runs `task` on a background thread and reports its result back to the UI.
+    ///
+    /// The task is given an observer whose events move the progress bar, and a
+    /// token that the Cancel button cancels if the task is `cancellable`.
+    fn spawn_operation<F>(&mut self, message: &str, cancellable: bool, task: F)
+    where
+        F: FnOnce(ChannelProgress, CancellationToken) -> std::io::Result<Outcome> + Send + 'static,
+    {
+        self.process_state = ProcessState::Running {
+            progress: 0.0,
+            message: message.to_owned(),
+            cancellable,
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        self.progress_events = Some(receiver);
+        self.progress_total = 0;
+        self.progress_done = 0;
+        self.cancel = CancellationToken::new();
+        let cancel = self.cancel.clone();
+
+        let result_clone = Arc::clone(&self.operation_result);
+        thread::spawn(move || {
+            let result = task(ChannelProgress::new(sender), cancel).map_err(|e| e.to_string());
+            let mut guard = result_clone.lock().unwrap();
+            *guard = Some(result);
+        });
+    }
 }
 
 impl App for FileOrganizerApp {
@@ -94,15 +338,88 @@ impl App for FileOrganizerApp {
             }
         }
 
+        if let Some(receiver) = &self.progress_events {
+            for event in receiver.try_iter() {
+                match event {
+                    ProgressEvent::Started { total } => self.progress_total = total,
+                    ProgressEvent::ItemProcessed { .. }
+                    | ProgressEvent::ItemSkipped { .. }
+                    | ProgressEvent::ItemFailed(_) => self.progress_done += 1,
+                    ProgressEvent::Finished => {}
+                }
+            }
+            if let ProcessState::Running { progress, .. } = &mut self.process_state {
+                if self.progress_total > 0 {
+                    *progress = self.progress_done as f32 / self.progress_total as f32;
+                }
+            }
+        }
+        if matches!(self.process_state, ProcessState::Running { .. }) {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+
         if let Some(result) = self.operation_result.lock().unwrap().take() {
+            self.progress_events = None;
             self.process_state = match result {
-                Ok(()) => ProcessState::Complete {
-                    success: true,
-                    message: "Files organized successfully!".to_owned(),
-                },
+                Ok(Outcome::Organized(report)) => {
+                    self.last_run_id = Some(report.run_id.clone());
+                    if report.cancelled {
+                        ProcessState::Complete {
+                            success: false,
+                            message: format!(
+                                "Cancelled after organizing {} files. Use Undo to reverse them.",
+                                report.operations.len()
+                            ),
+                            failures: report.failures,
+                        }
+                    } else if report.failures.is_empty() {
+                        ProcessState::Complete {
+                            success: true,
+                            message: format!(
+                                "Files organized successfully! {} files ({}) in {:.1?}, {} items skipped, {} filtered out.",
+                                report.operations.len(),
+                                format_bytes(report.bytes_moved + report.bytes_copied),
+                                report.elapsed,
+                                report.skipped.len(),
+                                report.filtered.len()
+                            ),
+                            failures: Vec::new(),
+                        }
+                    } else {
+                        ProcessState::Complete {
+                            success: false,
+                            message: format!(
+                                "Files organized, but {} items could not be organized:",
+                                report.failures.len()
+                            ),
+                            failures: report.failures,
+                        }
+                    }
+                }
+                Ok(Outcome::Undone(run_id)) => {
+                    self.last_run_id = None;
+                    ProcessState::Complete {
+                        success: true,
+                        message: format!("Run {} undone.", run_id),
+                        failures: Vec::new(),
+                    }
+                }
+                Ok(Outcome::NeedsConfirmation(plan)) => {
+                    let message = format!(
+                        "This would organize {} files. Review the planned changes and apply them to continue.",
+                        plan.operations.len()
+                    );
+                    self.pending_plan = Some(*plan);
+                    ProcessState::Complete { success: false, message, failures: Vec::new() }
+                }
+                Ok(Outcome::Planned(plan)) => {
+                    self.pending_plan = Some(*plan);
+                    ProcessState::Idle
+                }
                 Err(err) => ProcessState::Complete {
                     success: false,
                     message: format!("Error: {}", err),
+                    failures: Vec::new(),
                 },
             }
         }
@@ -120,7 +437,7 @@ impl App for FileOrganizerApp {
                             .strong(),
                     );
                     ui.add_space(10.0);
-                    ui.label("Organize your files by file extensions");
+                    ui.label("Organize your files by category and file extension");
                     ui.add_space(20.0);
                 });
 
@@ -186,6 +503,41 @@ impl App for FileOrganizerApp {
                 });
                 ui.add_space(20.0);
 
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.label(
+                            RichText::new("Destination directory (optional)")
+                                .size(16.0)
+                                .strong(),
+                        );
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut self.destination_root)
+                                    .hint_text("Organize in place...")
+                                    .desired_width(text_width),
+                            );
+                            // Use Noto Emoji for the folder icon.
+                            if ui
+                                .button(
+                                    RichText::new("📁").font(
+                                        FontId::new(14.0, FontFamily::Name("noto_emoji".into()))
+                                    ),
+                                )
+                                .clicked()
+                            {
+                                self.destination_browse_dialog_open = true;
+                            }
+                            if self.destination_browse_dialog_open {
+                                if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                                    self.destination_root = path.display().to_string();
+                                }
+                                self.destination_browse_dialog_open = false;
+                            }
+                        });
+                    });
+                });
+                ui.add_space(20.0);
+
                 ui.group(|ui| {
                     ui.vertical(|ui| {
                         ui.label(RichText::new("Log file path").size(16.0).strong());
@@ -242,14 +594,143 @@ impl App for FileOrganizerApp {
                     ui.vertical(|ui| {
                         ui.label(RichText::new("Options").size(16.0).strong());
                         ui.checkbox(&mut self.copy_files, "Copy files instead of moving them");
+                        ui.checkbox(&mut self.verify_transfers, "Verify copied files with checksums")
+                            .on_hover_text("Applies to copies and to moves onto another disk");
+                        ui.checkbox(&mut self.continue_on_error, "Continue when a file cannot be organized");
                         ui.checkbox(&mut self.include_hidden, "Include hidden files");
+                        ui.checkbox(&mut self.allow_project_root, "Allow organizing a project folder")
+                            .on_hover_text(self.settings.project_markers.join(", "));
+                        ui.horizontal(|ui| {
+                            ui.label("Symbolic links:");
+                            egui::ComboBox::from_id_salt("symlink_policy")
+                                .selected_text(self.symlink_policy.label())
+                                .show_ui(ui, |ui| {
+                                    for policy in SymlinkPolicy::ALL {
+                                        ui.selectable_value(&mut self.symlink_policy, policy, policy.label());
+                                    }
+                                });
+                        });
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.limit_age, "Only organize files older than");
+                            ui.add_enabled(self.limit_age, egui::DragValue::new(&mut self.min_age_days).range(1..=3650));
+                            ui.label("days");
+                        });
+                        ui.checkbox(&mut self.recursive, "Include subdirectories");
+                        if self.recursive {
+                            ui.indent("recursive_options", |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.checkbox(&mut self.limit_depth, "Limit depth to");
+                                    ui.add_enabled(
+                                        self.limit_depth,
+                                        egui::DragValue::new(&mut self.max_depth).range(1..=32),
+                                    );
+                                    ui.label("levels");
+                                });
+                                for layout in RecursiveLayout::ALL {
+                                    ui.radio_value(&mut self.recursive_layout, layout, layout.label());
+                                }
+                                ui.horizontal(|ui| {
+                                    ui.label("Project folders:").on_hover_text(self.settings.project_markers.join(", "));
+                                    egui::ComboBox::from_id_salt("project_policy")
+                                        .selected_text(self.project_policy.label())
+                                        .show_ui(ui, |ui| {
+                                            for policy in ProjectPolicy::ALL {
+                                                ui.selectable_value(&mut self.project_policy, policy, policy.label());
+                                            }
+                                        });
+                                });
+                            });
+                        }
+                        ui.checkbox(&mut self.use_template, "Custom folder layout")
+                            .on_hover_text(
+                                "Placeholders: {category}, {ext}, {year}, {month:02}, {day:02}, \
+                                 {size_bucket}, {first_letter}, {parent}",
+                            );
+                        if self.use_template {
+                            ui.indent("template_options", |ui| {
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut self.destination_template)
+                                        .hint_text("{category}/{year}")
+                                        .desired_width(220.0),
+                                );
+                                if let Err(err) = self.destination_template() {
+                                    ui.colored_label(Color32::RED, err);
+                                }
+                            });
+                        }
+                        ui.checkbox(&mut self.organize_by_date, "Organize into date folders");
+                        if self.organize_by_date {
+                            ui.indent("date_options", |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.label("Date from:");
+                                    egui::ComboBox::from_id_salt("date_source")
+                                        .selected_text(self.date_source.label())
+                                        .show_ui(ui, |ui| {
+                                            for source in DateSource::ALL {
+                                                ui.selectable_value(&mut self.date_source, source, source.label());
+                                            }
+                                        });
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("Folders:");
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut self.date_pattern)
+                                            .hint_text("{year}/{month:02}")
+                                            .desired_width(160.0),
+                                    );
+                                });
+                                if let Err(err) = self.date_pattern() {
+                                    ui.colored_label(Color32::RED, err);
+                                }
+                                ui.checkbox(&mut self.date_with_category, "Inside category folders");
+                            });
+                        }
+                        let category_names: Vec<_> = self.categories.iter().map(|c| c.name.as_str()).collect();
+                        ui.checkbox(&mut self.use_categories, "Group extensions into categories")
+                            .on_hover_text(category_names.join(", "));
+                        let sidecar_names: Vec<_> = self.sidecars.iter().map(|rule| rule.name.as_str()).collect();
+                        ui.checkbox(&mut self.use_sidecars, "Keep sidecar files with their primary file")
+                            .on_hover_text(sidecar_names.join(", "));
+                        ui.horizontal(|ui| {
+                            ui.label("Detect file types from content:");
+                            egui::ComboBox::from_id_salt("content_detection")
+                                .selected_text(self.content_detection.label())
+                                .show_ui(ui, |ui| {
+                                    for mode in ContentDetection::ALL {
+                                        ui.selectable_value(&mut self.content_detection, mode, mode.label());
+                                    }
+                                });
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("When a file already exists:");
+                            egui::ComboBox::from_id_salt("conflict_policy")
+                                .selected_text(self.conflict_policy.label())
+                                .show_ui(ui, |ui| {
+                                    for policy in ConflictPolicy::ALL {
+                                        ui.selectable_value(&mut self.conflict_policy, policy, policy.label());
+                                    }
+                                });
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Identical files:");
+                            egui::ComboBox::from_id_salt("duplicate_policy")
+                                .selected_text(self.duplicate_policy.label())
+                                .show_ui(ui, |ui| {
+                                    for policy in DuplicatePolicy::ALL {
+                                        ui.selectable_value(&mut self.duplicate_policy, policy, policy.label());
+                                    }
+                                });
+                        });
+                        if ui.button("Save as default settings").clicked() {
+                            self.save_settings();
+                        }
                     });
                 });
                 ui.add_space(20.0);
 
                 match &self.process_state {
                     ProcessState::Idle => {}
-                    ProcessState::Running { progress, message } => {
+                    ProcessState::Running { progress, message, cancellable } => {
                         ui.group(|ui| {
                             ui.vertical(|ui| {
                                 ui.label(message);
@@ -259,13 +740,33 @@ impl App for FileOrganizerApp {
                                         .show_percentage()
                                         .desired_width(text_width),
                                 );
+                                if *cancellable {
+                                    let cancelling = self.cancel.is_cancelled();
+                                    let label = if cancelling { "Cancelling..." } else { "Cancel" };
+                                    if ui.add_enabled(!cancelling, egui::Button::new(label)).clicked() {
+                                        self.cancel.cancel();
+                                    }
+                                }
                             });
                         });
                     }
-                    ProcessState::Complete { success, message } => {
+                    ProcessState::Complete { success, message, failures } => {
                         ui.group(|ui| {
-                            let color = if *success { Color32::GREEN } else { Color32::RED };
-                            ui.colored_label(color, message);
+                            ui.vertical(|ui| {
+                                let color = if *success { Color32::GREEN } else { Color32::RED };
+                                ui.colored_label(color, message);
+                                if !failures.is_empty() {
+                                    egui::ScrollArea::vertical()
+                                        .id_salt("failures")
+                                        .max_height(150.0)
+                                        .show(ui, |ui| {
+                                            for failure in failures {
+                                                ui.label(failure.path.display().to_string())
+                                                    .on_hover_text(&failure.message);
+                                            }
+                                        });
+                                }
+                            });
                         });
                     }
                 }
@@ -274,7 +775,63 @@ impl App for FileOrganizerApp {
                 let can_execute = !self.directory.is_empty()
                     && !self.log_file.is_empty()
                     && !self.others_directory.is_empty()
+                    && (!self.organize_by_date || self.date_pattern().is_ok())
+                    && (!self.use_template || self.destination_template().is_ok())
                     && !matches!(self.process_state, ProcessState::Running { .. });
+
+                if let Some(plan) = &self.pending_plan {
+                    let mut apply_clicked = false;
+                    let mut discard_clicked = false;
+                    ui.group(|ui| {
+                        ui.vertical(|ui| {
+                            ui.label(RichText::new("Planned changes").size(16.0).strong());
+                            egui::ScrollArea::vertical()
+                                .max_height(200.0)
+                                .show(ui, |ui| {
+                                    for directory in &plan.directories {
+                                        ui.label(format!("Create {}", directory.display()));
+                                    }
+                                    for operation in &plan.operations {
+                                        ui.label(operation.to_string());
+                                    }
+                                    for link in &plan.links {
+                                        ui.label(link.to_string());
+                                    }
+                                    for item in &plan.skipped {
+                                        ui.weak(format!("Skip {} ({})", item.path.display(), item.reason));
+                                    }
+                                    for group in &plan.duplicates {
+                                        for duplicate in &group.duplicates {
+                                            ui.weak(format!(
+                                                "Duplicate {} of {}",
+                                                duplicate.display(),
+                                                group.original.display()
+                                            ));
+                                        }
+                                    }
+                                });
+                            ui.horizontal(|ui| {
+                                apply_clicked = ui
+                                    .add_enabled(can_execute && !plan.is_empty(), egui::Button::new("Apply plan"))
+                                    .clicked();
+                                discard_clicked = ui.button("Discard").clicked();
+                            });
+                        });
+                    });
+                    ui.add_space(20.0);
+
+                    if apply_clicked {
+                        let plan = self.pending_plan.take().unwrap();
+                        let config = self.directory_config();
+                        self.spawn_operation("Applying plan...", true, move |mut progress, cancel| {
+                            let config = config.map_err(std::io::Error::other)?;
+                            apply_plan_with(&plan, &config, &mut progress, &cancel).map(Box::new).map(Outcome::Organized)
+                        });
+                    } else if discard_clicked {
+                        self.pending_plan = None;
+                    }
+                }
+
                 if ui
                     .add_enabled(
                         can_execute,
@@ -295,28 +852,55 @@ impl App for FileOrganizerApp {
                     .clicked()
                 {
                     let dir = self.directory.clone();
-                    let config = Config {
-                        copy: self.copy_files,
-                        include_hidden: self.include_hidden,
-                        others_directory: self.others_directory.clone(),
-                        log_file: self.log_file.clone(),
-                    };
-
-                    self.process_state = ProcessState::Running {
-                        progress: 0.0,
-                        message: "Organizing files...".to_owned(),
-                    };
-
-                    let result_clone = Arc::clone(&self.operation_result);
-                    thread::spawn(move || {
-                        let result = match organise_files(&dir, &config) {
-                            Ok(()) => Ok(()),
-                            Err(e) => Err(e.to_string()),
-                        };
-                        let mut guard = result_clone.lock().unwrap();
-                        *guard = Some(result);
+                    let config = self.directory_config();
+                    self.pending_plan = None;
+                    self.spawn_operation("Organizing files...", true, move |mut progress, cancel| {
+                        let config = config.map_err(std::io::Error::other)?;
+                        let plan = plan_with(&dir, &config, &cancel, &|_| None)?;
+                        if plan.needs_confirmation(&config) {
+                            return Ok(Outcome::NeedsConfirmation(Box::new(plan)));
+                        }
+                        apply_plan_with(&plan, &config, &mut progress, &cancel).map(Box::new).map(Outcome::Organized)
+                    });
+                }
+                ui.add_space(10.0);
+
+                if ui
+                    .add_enabled(
+                        can_execute,
+                        egui::Button::new(RichText::new("PREVIEW").size(14.0).strong())
+                            .min_size(Vec2::new(text_width, 30.0))
+                            .corner_radius(egui::CornerRadius::same(10)),
+                    )
+                    .clicked()
+                {
+                    let dir = self.directory.clone();
+                    let config = self.directory_config();
+                    self.pending_plan = None;
+                    self.spawn_operation("Planning...", true, move |_, cancel| {
+                        let config = config.map_err(std::io::Error::other)?;
+                        plan_with(&dir, &config, &cancel, &|_| None).map(Box::new).map(Outcome::Planned)
                     });
                 }
+
+                if let Some(run_id) = self.last_run_id.clone() {
+                    ui.add_space(10.0);
+                    let can_undo = !matches!(self.process_state, ProcessState::Running { .. });
+                    if ui
+                        .add_enabled(
+                            can_undo,
+                            egui::Button::new(RichText::new("UNDO LAST RUN").size(14.0).strong())
+                                .min_size(Vec2::new(text_width, 30.0))
+                                .corner_radius(egui::CornerRadius::same(10)),
+                        )
+                        .clicked()
+                    {
+                        let config = self.config();
+                        self.spawn_operation("Undoing last run...", false, move |_, _| {
+                            undo_run(&run_id, &config).map(|()| Outcome::Undone(run_id))
+                        });
+                    }
+                }
                 ui.add_space(20.0);
             });
         });
@@ -370,7 +954,7 @@ pub fn run_gui() -> Result<(), eframe::Error> {
             // Apply the updated font definitions.
             cc.egui_ctx.set_fonts(fonts);
 
-            Ok(Box::new(FileOrganizerApp::default()))
+            Ok(Box::new(FileOrganizerApp::new()))
         }),
     )
 }
@@ -0,0 +1,356 @@
+//! Machine-readable run journals and undo support.
+//!
+//! Every time a plan is applied, a journal file named after the run id is written
+//! to `Config::journal_directory`. The journal is a JSON Lines file: the first line
+//! is a [`RunHeader`] and every following line is a [`JournalEntry`], appended as
+//! soon as the corresponding change has been made on disk. Because entries are
+//! written as the run progresses, even an interrupted run can be undone.
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::plan::{Link, Operation, TransferMode};
+use crate::transfer::{move_file, replace_with_copy, restore_link};
+use crate::Config;
+
+/// Identifying information written at the top of every journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunHeader {
+    /// Unique identifier of the run, also used as the journal file name.
+    pub run_id: String,
+    /// When the run started, in RFC 3339 format.
+    pub timestamp: String,
+    /// The directory that was organized.
+    pub root: PathBuf,
+}
+
+/// A single change recorded in a journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum JournalEntry {
+    /// A directory that did not exist before the run was created.
+    CreatedDirectory(PathBuf),
+    /// A file was moved or copied.
+    Transferred(Operation),
+    /// A duplicate was replaced with a hard link to an identical file.
+    Linked(Link),
+    /// The entry at the given position among the entries has been reversed by
+    /// an undo that did not finish. Retrying the undo skips it.
+    Reverted(usize),
+    /// The run has been undone.
+    Undone {
+        /// When the run was undone, in RFC 3339 format.
+        timestamp: String,
+    },
+}
+
+/// A run journal as read back from disk.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    /// Identifying information about the run.
+    pub header: RunHeader,
+    /// Every change made by the run, in the order it happened.
+    pub entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// Returns `true` if the run has already been undone.
+    pub fn is_undone(&self) -> bool {
+        self.entries.iter().any(|entry| matches!(entry, JournalEntry::Undone { .. }))
+    }
+}
+
+/// Appends entries to the journal of a run in progress.
+pub(crate) struct JournalWriter {
+    file: File,
+    run_id: String,
+}
+
+impl JournalWriter {
+    /// Creates a new journal for a run organizing `root` and writes its header.
+    pub(crate) fn create(config: &Config, root: &Path) -> io::Result<Self> {
+        fs::create_dir_all(&config.journal_directory)?;
+
+        let now = Local::now();
+        let run_id = now.format("%Y%m%d-%H%M%S%3f").to_string();
+        let file = OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(journal_path(config, &run_id))?;
+
+        let mut writer = Self { file, run_id: run_id.clone() };
+        let header = RunHeader {
+            run_id,
+            timestamp: now.to_rfc3339(),
+            root: root.to_path_buf(),
+        };
+        writer.write_line(&header)?;
+        Ok(writer)
+    }
+
+    /// The identifier of the run being journaled.
+    pub(crate) fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Records a change that has just been made on disk.
+    pub(crate) fn record(&mut self, entry: &JournalEntry) -> io::Result<()> {
+        self.write_line(entry)
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> io::Result<()> {
+        let line = serde_json::to_string(value)?;
+        writeln!(self.file, "{}", line)?;
+        self.file.flush()
+    }
+}
+
+/// Path of the journal file for `run_id`.
+fn journal_path(config: &Config, run_id: &str) -> PathBuf {
+    Path::new(&config.journal_directory).join(format!("{}.jsonl", run_id))
+}
+
+/// Reads the journal of a previous run.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the journal cannot be read or is malformed.
+pub fn read_journal(run_id: &str, config: &Config) -> io::Result<Journal> {
+    let reader = BufReader::new(File::open(journal_path(config, run_id))?);
+    let mut lines = reader.lines();
+
+    let header = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)?,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("journal for run {} is empty", run_id),
+            ))
+        }
+    };
+
+    let mut entries = Vec::new();
+    for line in lines {
+        let line = line?;
+        if !line.trim().is_empty() {
+            entries.push(serde_json::from_str(&line)?);
+        }
+    }
+
+    Ok(Journal { header, entries })
+}
+
+/// Lists the headers of all journaled runs, oldest first.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the journal directory or a journal cannot be read.
+pub fn list_runs(config: &Config) -> io::Result<Vec<RunHeader>> {
+    let directory = Path::new(&config.journal_directory);
+    if !directory.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut runs = Vec::new();
+    for item in fs::read_dir(directory)? {
+        let path = item?.path();
+        if path.extension().is_some_and(|extension| extension == "jsonl") {
+            let run_id = path.file_stem().unwrap().to_string_lossy().into_owned();
+            runs.push(read_journal(&run_id, config)?.header);
+        }
+    }
+
+    runs.sort_by(|a, b| a.run_id.cmp(&b.run_id));
+    Ok(runs)
+}
+
+/// Reverses a completed (or interrupted) organize run.
+///
+/// Moved files and directories are moved back to where they came from, copies
+/// made in copy mode are deleted, duplicates replaced with hard links get their own copy of the
+/// content again, and directories created by the run are removed if they are empty.
+/// Changes are reversed in the opposite order to which they were made, and each
+/// step is logged to the log file named in `config`. Every reversed change is
+/// recorded in the journal straight away, so an undo that fails partway can be
+/// retried once the problem is fixed and carries on where it stopped.
+///
+/// # Arguments
+///
+/// * `run_id` - The identifier of the run to undo.
+/// * `config` - A reference to a `Config` structure with operation settings.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the journal cannot be read, the run was already
+/// undone, or an error occurs while restoring files.
+pub fn undo_run(run_id: &str, config: &Config) -> io::Result<()> {
+    let journal = read_journal(run_id, config)?;
+    if journal.is_undone() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("run {} has already been undone", run_id),
+        ));
+    }
+
+    let mut log_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&config.log_file)?;
+    let mut journal_file = OpenOptions::new().append(true).open(journal_path(config, run_id))?;
+
+    let reverted: HashSet<usize> = journal
+        .entries
+        .iter()
+        .filter_map(|entry| match entry {
+            JournalEntry::Reverted(index) => Some(*index),
+            _ => None,
+        })
+        .collect();
+
+    for (index, entry) in journal.entries.iter().enumerate().rev() {
+        if reverted.contains(&index) {
+            continue;
+        }
+        match entry {
+            JournalEntry::Transferred(operation) => match operation.mode {
+                TransferMode::Move => {
+                    if operation.source.exists() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("cannot restore {}: a file already exists there", operation.source.display()),
+                        ));
+                    }
+                    match &operation.link_target {
+                        Some(target) => restore_link(&operation.destination, &operation.source, target)?,
+                        None => {
+                            move_file(&operation.destination, &operation.source, config.verify_transfers)?;
+                        }
+                    }
+                    writeln!(
+                        log_file,
+                        "Undo: moved {} back to {}",
+                        operation.destination.display(),
+                        operation.source.display()
+                    )?;
+                }
+                TransferMode::Copy => {
+                    // Project directories are copied whole.
+                    if fs::symlink_metadata(&operation.destination)?.is_dir() {
+                        fs::remove_dir_all(&operation.destination)?;
+                    } else {
+                        fs::remove_file(&operation.destination)?;
+                    }
+                    writeln!(log_file, "Undo: removed copy {}", operation.destination.display())?;
+                }
+            },
+            JournalEntry::Linked(link) => {
+                replace_with_copy(&link.path, config.verify_transfers)?;
+                writeln!(log_file, "Undo: unlinked {} from {}", link.path.display(), link.target.display())?;
+            }
+            JournalEntry::CreatedDirectory(directory) => match fs::remove_dir(directory) {
+                Ok(()) => writeln!(log_file, "Undo: removed directory {}", directory.display())?,
+                // The directory may have gained other files since the run.
+                Err(_) => writeln!(log_file, "Undo: kept non-empty directory {}", directory.display())?,
+            },
+            JournalEntry::Reverted(_) | JournalEntry::Undone { .. } => continue,
+        }
+        let line = serde_json::to_string(&JournalEntry::Reverted(index))?;
+        writeln!(journal_file, "{}", line)?;
+    }
+
+    let line = serde_json::to_string(&JournalEntry::Undone { timestamp: Local::now().to_rfc3339() })?;
+    writeln!(journal_file, "{}", line)?;
+    writeln!(log_file, "Run {} undone.\n", run_id)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::organise_files;
+    use tempfile::TempDir;
+
+    /// Creates a directory to organize, with the journal and log kept beside it.
+    fn setup() -> (TempDir, PathBuf, Config) {
+        let scratch = tempfile::tempdir().unwrap();
+        let root = scratch.path().join("inbox");
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("notes.txt"), "notes").unwrap();
+        fs::write(root.join("photo.jpg"), "photo").unwrap();
+
+        let config = Config {
+            journal_directory: scratch.path().join("journal").to_string_lossy().into_owned(),
+            log_file: scratch.path().join("fileorg.log").to_string_lossy().into_owned(),
+            ..Config::default()
+        };
+        (scratch, root, config)
+    }
+
+    /// The names of the entries of `directory`, sorted.
+    fn contents(directory: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(directory)
+            .unwrap()
+            .map(|item| item.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn undo_restores_the_directory() {
+        let (_scratch, root, config) = setup();
+        let report = organise_files(&root.to_string_lossy(), &config).unwrap();
+        assert_eq!(report.operations.len(), 2);
+        assert_eq!(contents(&root), ["Documents", "Images"]);
+
+        undo_run(&report.run_id, &config).unwrap();
+        assert_eq!(contents(&root), ["notes.txt", "photo.jpg"]);
+        assert_eq!(fs::read_to_string(root.join("notes.txt")).unwrap(), "notes");
+        assert!(read_journal(&report.run_id, &config).unwrap().is_undone());
+
+        let again = undo_run(&report.run_id, &config).unwrap_err();
+        assert_eq!(again.kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn undo_restores_relative_links_exactly() {
+        let (_scratch, root, config) = setup();
+        std::os::unix::fs::symlink("./notes.txt", root.join("shortcut.txt")).unwrap();
+        let config = Config { symlink_policy: crate::SymlinkPolicy::MoveLink, ..config };
+
+        let report = organise_files(&root.to_string_lossy(), &config).unwrap();
+        assert!(root.join("Documents/shortcut.txt").is_symlink());
+
+        undo_run(&report.run_id, &config).unwrap();
+        assert_eq!(fs::read_link(root.join("shortcut.txt")).unwrap(), Path::new("./notes.txt"));
+        assert_eq!(contents(&root), ["notes.txt", "photo.jpg", "shortcut.txt"]);
+    }
+
+    #[test]
+    fn undo_resumes_after_a_partial_failure() {
+        let (_scratch, root, config) = setup();
+        let report = organise_files(&root.to_string_lossy(), &config).unwrap();
+
+        // Changes are undone last to first, so blocking the first file lets the
+        // second be restored before the undo fails.
+        let [first, second] = &report.operations[..] else { panic!("expected two operations") };
+        fs::write(&first.source, "in the way").unwrap();
+        let err = undo_run(&report.run_id, &config).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(second.source.exists() && !second.destination.exists());
+        assert!(first.destination.exists());
+        assert!(!read_journal(&report.run_id, &config).unwrap().is_undone());
+
+        fs::remove_file(&first.source).unwrap();
+        undo_run(&report.run_id, &config).unwrap();
+        assert_eq!(contents(&root), ["notes.txt", "photo.jpg"]);
+        assert_eq!(fs::read_to_string(root.join("notes.txt")).unwrap(), "notes");
+        assert_eq!(fs::read_to_string(root.join("photo.jpg")).unwrap(), "photo");
+        assert!(read_journal(&report.run_id, &config).unwrap().is_undone());
+    }
+}
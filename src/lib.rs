@@ -1,38 +1,61 @@
 //! The core library module for the File Organizer.
 //! It provides the configuration structure and the file organization logic.
 
-use indicatif::{ProgressBar, ProgressStyle};
-use serde::Deserialize;
-use std::fs::{self, OpenOptions};
-use std::io::Write;
+pub mod cancel;
+pub mod category;
+pub mod cli;
+pub mod config;
+pub mod conflict;
+pub mod date;
+mod digest;
+pub mod duplicate;
+pub mod filter;
 pub mod gui;
-
-use std::path::Path;
-use std::time::Instant;
-use chrono::Local;
-
-/// Configuration options for organizing files.
-/// Users can specify whether to copy files instead of moving them,
-/// whether to include hidden files, the name of the directory to store files
-/// without extensions, and the path to the log file.
-#[derive(Deserialize)]
-pub struct Config {
-    /// If true, files will be copied; otherwise, they will be moved.
-    pub copy: bool,
-    /// If true, hidden files will be included.
-    pub include_hidden: bool,
-    /// Directory name where files without extensions will be stored.
-    pub others_directory: String,
-    /// Path to the log file where operations are recorded.
-    pub log_file: String,
-}
+pub mod journal;
+pub mod plan;
+pub mod progress;
+pub mod project;
+pub mod report;
+mod safety;
+pub mod sidecar;
+pub mod sniff;
+pub mod symlink;
+pub mod template;
+mod transfer;
+pub mod watch;
+
+pub use cancel::CancellationToken;
+pub use category::{default_categories, default_compound_extensions, CategoryRule};
+pub use config::{Config, ConfigError, DIRECTORY_CONFIG_FILE, USER_ONLY_KEYS};
+pub use conflict::{ConflictDecision, ConflictOutcome, ConflictPolicy};
+pub use date::{DateLayout, DateSource};
+pub use duplicate::{DuplicateGroup, DuplicatePolicy};
+pub use filter::{AgeSource, IGNORE_FILE};
+pub use journal::{list_runs, read_journal, undo_run, Journal, JournalEntry, RunHeader};
+pub use plan::{
+    apply_plan, apply_plan_with, plan_organisation, Link, Operation, Plan, RecursiveLayout, SkipReason, SkippedItem,
+    TransferMode,
+};
+pub use progress::{ChannelProgress, NoProgress, ProgressEvent, ProgressObserver, TerminalProgress};
+pub use project::{default_project_markers, ProjectPolicy};
+pub use report::{FailedItem, RunReport};
+pub use sidecar::{default_sidecar_rules, SidecarRule};
+pub use sniff::{sniff_extension, ContentDetection};
+pub use symlink::SymlinkPolicy;
+pub use template::{Template, TemplateError};
+pub use watch::{watch_directory, WatchSettings};
 
 /// Organizes files in the given directory according to their file extensions.
 ///
 /// Files are either moved or copied based on the configuration provided.
-/// Files without an extension are placed in a user-defined directory.
+/// Files without an extension are placed in a user-defined directory. The
+/// folders are created in `config.destination_root` if it is set, which may be
+/// on another file system.
 /// Progress and timing information is logged to the specified log file.
 ///
+/// This is a shorthand for [`plan_organisation`] followed by [`apply_plan`],
+/// and likewise returns a report of the run, including the id of its journal.
+///
 /// # Arguments
 ///
 /// * `directory` - The directory containing files to organize.
@@ -41,134 +64,24 @@ pub struct Config {
 /// # Errors
 ///
 /// Returns an `std::io::Error` if an error occurs during file system operations.
-pub fn organise_files(directory: &str, config: &Config) -> std::io::Result<()> {
-    // Start measuring total operation time.
-    let start_time = Instant::now();
-
-    // Open or create the log file.
-    let mut log_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&config.log_file)?;
-
-    // List items in the directory.
-    let items: Vec<_> = fs::read_dir(directory)?.collect();
-    let pb_style = ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} {msg}")
-        .unwrap()
-        .progress_chars("█▉▍ ");
-
-    let pb = ProgressBar::new(items.len() as u64);
-    pb.set_style(pb_style);
-
-    // Iterate over each file/directory in the given directory.
-    for item in items {
-        let item = item?;
-        let path = item.path();
-
-        if path.is_dir() {
-            writeln!(log_file, "Skipping subdirectory: {}", path.display())?;
-            pb.inc(1);
-            continue;
-        }
-
-        // Skip hidden files if not included.
-        if !config.include_hidden && path.file_name().unwrap().to_string_lossy().starts_with('.') {
-            writeln!(log_file, "Skipping hidden file: {}", path.display())?;
-            pb.inc(1);
-            continue;
-        }
-
-        // Handle files with extensions.
-        if let Some(extension) = path.extension() {
-            let extension = extension.to_string_lossy().to_lowercase();
-            let destination_directory = Path::new(directory).join(&extension).with_extension("");
-
-            if !destination_directory.exists() {
-                fs::create_dir(&destination_directory)?;
-            }
-
-            // Skip if the file is already in the correct directory.
-            if path.parent() == Some(destination_directory.as_path()) {
-                pb.inc(1);
-                continue;
-            }
-
-            // Measure time for the copy/move operation.
-            let start_file_time = Instant::now();
-            let file_name = path.file_name().unwrap();
-            let destination_file_path = destination_directory.join(file_name);
-
-            if config.copy {
-                fs::copy(&path, &destination_file_path)?;
-                let elapsed_time = start_file_time.elapsed();
-                writeln!(
-                    log_file,
-                    "Copied {} to {} in {:.2?}",
-                    file_name.to_string_lossy(),
-                    destination_directory.display(),
-                    elapsed_time
-                )?;
-            } else {
-                fs::rename(&path, &destination_file_path)?;
-                let elapsed_time = start_file_time.elapsed();
-                writeln!(
-                    log_file,
-                    "Moved {} to {} in {:.2?}",
-                    file_name.to_string_lossy(),
-                    destination_directory.display(),
-                    elapsed_time
-                )?;
-            }
-        } else {
-            // Handle files without extensions.
-            let others_directory = Path::new(directory).join(&config.others_directory);
-
-            if !others_directory.exists() {
-                fs::create_dir(&others_directory)?;
-            }
-
-            let file_name = path.file_name().unwrap();
-            let destination_file_path = others_directory.join(file_name);
-
-            let start_file_time = Instant::now();
-
-            if config.copy {
-                fs::copy(&path, &destination_file_path)?;
-                let elapsed_time = start_file_time.elapsed();
-                writeln!(
-                    log_file,
-                    "Copied {} to {} in {:.2?}",
-                    file_name.to_string_lossy(),
-                    others_directory.display(),
-                    elapsed_time
-                )?;
-            } else {
-                fs::rename(&path, &destination_file_path)?;
-                let elapsed_time = start_file_time.elapsed();
-                writeln!(
-                    log_file,
-                    "Moved {} to {} in {:.2?}",
-                    file_name.to_string_lossy(),
-                    others_directory.display(),
-                    elapsed_time
-                )?;
-            }
-        }
-
-        pb.inc(1);
-    }
-
-    let total_elapsed_time = start_time.elapsed();
-    let current_time = Local::now();
-    writeln!(
-        log_file,
-        "Operation completed successfully at {} on {}. Total time taken: {:.2?}, {:?} files processed.\n",
-        current_time.format("%H:%M:%S").to_string(),
-        current_time.format("%A, %B %d, %Y").to_string(),
-        total_elapsed_time,
-        pb.length().unwrap_or(0),
-    )?;
+pub fn organise_files(directory: &str, config: &Config) -> std::io::Result<RunReport> {
+    let plan = plan_organisation(directory, config)?;
+    apply_plan(&plan, config)
+}
 
-    pb.finish_with_message("Done!");
-    Ok(())
-}
\ No newline at end of file
+/// Like [`organise_files`], but reports progress to `observer` instead of
+/// drawing a progress bar on the terminal, and stops once `cancel` is cancelled.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if an error occurs during file system operations,
+/// or one of kind `Interrupted` if cancelled while the directory is being scanned.
+pub fn organise_files_with(
+    directory: &str,
+    config: &Config,
+    observer: &mut dyn ProgressObserver,
+    cancel: &CancellationToken,
+) -> std::io::Result<RunReport> {
+    let plan = plan::plan_with(directory, config, cancel, &|_| None)?;
+    apply_plan_with(&plan, config, observer, cancel)
+}
@@ -1,10 +1,11 @@
 //! Entry point for the File Organizer application.
 
-mod gui;
-use fileorg::{organise_files, Config};
+use clap::Parser;
+use fileorg::cli::{self, Cli};
 
 fn main() {
-    if let Err(e) = gui::run_gui() {
-        eprintln!("Error running GUI: {}", e);
+    if let Err(e) = cli::run(Cli::parse()) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,1327 @@
+//! Planning and applying file organization.
+//!
+//! Organizing a directory happens in two phases: [`plan_organisation`] inspects
+//! the directory and produces a [`Plan`] describing every directory that will be
+//! created and every file that will be moved or copied, without touching the disk.
+//! [`apply_plan`] then carries out a (possibly reviewed) plan.
+//!
+//! Once every file has been found, files with identical content are grouped and
+//! dealt with according to `Config::duplicate_policy` before the remaining files
+//! are classified.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+use chrono::Local;
+
+use crate::cancel::CancellationToken;
+use crate::category::{category_for, extension_of, split_extension};
+use crate::conflict::{self, ConflictDecision, ConflictPolicy, Resolution};
+use crate::date::{file_date, DateSource};
+use crate::duplicate::{find_duplicates, DuplicateGroup, DuplicatePolicy};
+use crate::filter::{Filter, IGNORE_FILE};
+use crate::journal::{JournalEntry, JournalWriter};
+use crate::progress::{ProgressObserver, TerminalProgress};
+use crate::project::{is_project, ProjectPolicy};
+use crate::report::{FailedItem, RunReport};
+use crate::safety;
+use crate::sidecar::find_primaries;
+use crate::sniff::detect;
+use crate::symlink::SymlinkPolicy;
+use crate::template::TemplateValues;
+use crate::transfer::{
+    copy_directory, copy_file, copy_replacing, directory_size, move_file, move_replacing, relink, replace_with_hard_link,
+    Transfer,
+};
+use crate::{Config, DIRECTORY_CONFIG_FILE};
+
+/// How a file is transferred to its destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransferMode {
+    /// The file is moved, leaving nothing behind at the source.
+    Move,
+    /// The file is copied, leaving the source untouched.
+    Copy,
+}
+
+/// A single transfer of a file, or a whole project directory, from a source
+/// path to a destination path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    /// The file or project directory to be moved or copied.
+    pub source: PathBuf,
+    /// The full path the file will have once the operation is applied.
+    pub destination: PathBuf,
+    /// Whether the file is moved or copied.
+    pub mode: TransferMode,
+    /// Whether an existing file at the destination is replaced.
+    #[serde(default)]
+    pub overwrite: bool,
+    /// The category the file was classified as: a category name, the
+    /// extension, or the "others" directory.
+    #[serde(default)]
+    pub category: String,
+    /// For a symbolic link, its target exactly as stored in the link. The
+    /// transferred link may point at the same place differently, so undo
+    /// restores the link from this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<PathBuf>,
+}
+
+/// Replacing an organized duplicate with a hard link to an identical file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Link {
+    /// The duplicate that is replaced.
+    pub path: PathBuf,
+    /// The file it becomes a hard link to.
+    pub target: PathBuf,
+}
+
+/// How subdirectories are organized when organizing recursively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecursiveLayout {
+    /// Files from every level are gathered into the folders of the top-level directory.
+    #[default]
+    Flatten,
+    /// Each subdirectory gets its own folders, and files stay within their subdirectory.
+    InPlace,
+}
+
+impl RecursiveLayout {
+    /// Every layout, in the order they are offered to users.
+    pub const ALL: [RecursiveLayout; 2] = [RecursiveLayout::Flatten, RecursiveLayout::InPlace];
+
+    /// The name used for the layout in configuration files and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RecursiveLayout::Flatten => "flatten",
+            RecursiveLayout::InPlace => "in_place",
+        }
+    }
+
+    /// A short human-readable description of the layout.
+    pub fn label(&self) -> &'static str {
+        match self {
+            RecursiveLayout::Flatten => "Gather into top-level folders",
+            RecursiveLayout::InPlace => "Organize each subdirectory in place",
+        }
+    }
+}
+
+impl FromStr for RecursiveLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RecursiveLayout::ALL
+            .into_iter()
+            .find(|layout| layout.name() == s)
+            .ok_or_else(|| format!("unknown layout '{}', expected one of: flatten, in_place", s))
+    }
+}
+
+/// Why an item in the directory is left alone.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum SkipReason {
+    /// The item is a directory that is not organized.
+    Directory,
+    /// The item is a folder created by a previous run.
+    OrganizedDirectory,
+    /// The item is the destination root.
+    DestinationRoot,
+    /// The item is hidden and hidden files are not included.
+    Hidden,
+    /// The file already lives in its destination directory.
+    AlreadyInPlace,
+    /// The file is the per-directory configuration file.
+    ConfigFile,
+    /// The destination is taken and the conflict policy is to skip.
+    DestinationExists,
+    /// The destination holds a newer file.
+    ExistingIsNewer,
+    /// The destination holds a larger file.
+    ExistingIsLarger,
+    /// The destination holds an identical file.
+    Duplicate,
+    /// The file is still being written.
+    Incomplete,
+    /// The file is a download in progress, such as `.part` or `.crdownload`.
+    PartialDownload,
+    /// The file was already copied while watching the directory.
+    AlreadyCopied,
+    /// The item matches the given exclude pattern or ignore file pattern.
+    Excluded(String),
+    /// Include patterns are configured and the file matches none of them.
+    NotIncluded,
+    /// The file is smaller than the minimum size.
+    TooSmall,
+    /// The file is larger than the maximum size.
+    TooLarge,
+    /// The file is younger than the minimum age.
+    TooNew,
+    /// The file is older than the maximum age.
+    TooOld,
+    /// The file is a sidecar of a file that is not being moved.
+    StaysWithPrimary,
+    /// The directory is a project, which is never split up.
+    Project,
+    /// The item is a symbolic link and links are skipped.
+    Symlink,
+    /// The item is a symbolic link to the given target, which does not exist.
+    BrokenLink(PathBuf),
+    /// The item, or what a followed link points to, is already organized
+    /// through another path.
+    LinkedElsewhere,
+    /// The item is a followed link into a protected location such as a system directory.
+    ProtectedLinkTarget,
+    /// The item is a followed link to a directory containing the organized directory.
+    LinkToParent,
+}
+
+impl SkipReason {
+    /// Returns `true` if the item was filtered out by the size or age limits.
+    pub fn is_size_or_age(&self) -> bool {
+        matches!(self, SkipReason::TooSmall | SkipReason::TooLarge | SkipReason::TooNew | SkipReason::TooOld)
+    }
+}
+
+/// An item that will not be touched, together with the reason why.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedItem {
+    /// Path of the skipped item.
+    pub path: PathBuf,
+    /// Why the item is skipped.
+    pub reason: SkipReason,
+}
+
+/// Everything an organize run will do, computed before anything is changed on disk.
+#[derive(Debug, Clone, Serialize)]
+pub struct Plan {
+    /// The directory being organized.
+    pub root: PathBuf,
+    /// The directory the organized folders are created in: the destination
+    /// root if one is configured, otherwise `root` itself.
+    pub destination: PathBuf,
+    /// Directories that do not exist yet and will be created, in creation order.
+    pub directories: Vec<PathBuf>,
+    /// File transfers, in the order they will be applied.
+    pub operations: Vec<Operation>,
+    /// Items that will be left untouched.
+    pub skipped: Vec<SkippedItem>,
+    /// Destinations that were already taken, and how each was resolved.
+    pub conflicts: Vec<ConflictDecision>,
+    /// Groups of files with identical content, if duplicates are looked for.
+    pub duplicates: Vec<DuplicateGroup>,
+    /// Duplicates replaced with hard links once every file has been transferred.
+    pub links: Vec<Link>,
+    /// Destinations of all planned operations, for detecting collisions within the run.
+    #[serde(skip)]
+    planned_destinations: HashSet<PathBuf>,
+    /// Files and directories reached through followed links that lie outside
+    /// `root`. They are copied even when moving, so that organizing a directory
+    /// never takes anything away from elsewhere.
+    #[serde(skip)]
+    outside_root: HashSet<PathBuf>,
+}
+
+impl Plan {
+    pub(crate) fn new(root: &Path, destination: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            destination: destination.to_path_buf(),
+            directories: Vec::new(),
+            operations: Vec::new(),
+            skipped: Vec::new(),
+            conflicts: Vec::new(),
+            duplicates: Vec::new(),
+            links: Vec::new(),
+            planned_destinations: HashSet::new(),
+            outside_root: HashSet::new(),
+        }
+    }
+
+    /// Returns `true` if applying the plan would not change anything on disk.
+    pub fn is_empty(&self) -> bool {
+        self.directories.is_empty() && self.operations.is_empty() && self.links.is_empty()
+    }
+
+    /// Returns `true` if the plan transfers more files than a non-zero
+    /// `config.confirmation_threshold`, so the user should confirm it before it
+    /// is applied.
+    pub fn needs_confirmation(&self, config: &Config) -> bool {
+        config.confirmation_threshold > 0 && self.operations.len() > config.confirmation_threshold
+    }
+
+    /// Total number of items considered while planning.
+    pub fn item_count(&self) -> usize {
+        self.operations.len() + self.skipped.len()
+    }
+
+    /// Records that `directory` must be created, unless it exists or is already planned.
+    fn ensure_directory(&mut self, directory: &Path) {
+        if !directory.exists() && !self.directories.iter().any(|d| d == directory) {
+            self.directories.push(directory.to_path_buf());
+        }
+    }
+
+    fn skip(&mut self, path: PathBuf, reason: SkipReason) {
+        self.skipped.push(SkippedItem { path, reason });
+    }
+
+    /// Plans a transfer of `source` to `destination`, resolving any conflict
+    /// with an existing or already planned file according to `config.conflict_policy`.
+    fn transfer(&mut self, source: PathBuf, destination: PathBuf, category: String, config: &Config) -> io::Result<()> {
+        self.transfer_with(source, destination, category, config.conflict_policy, config)
+    }
+
+    /// Plans a transfer of the project directory `source` to `destination`.
+    /// Directories cannot be compared or overwritten like files, so a conflict
+    /// is resolved by renaming with a number unless the policy is to skip.
+    fn transfer_directory(
+        &mut self,
+        source: PathBuf,
+        destination: PathBuf,
+        category: String,
+        config: &Config,
+    ) -> io::Result<()> {
+        let policy = match config.conflict_policy {
+            ConflictPolicy::Skip => ConflictPolicy::Skip,
+            _ => ConflictPolicy::RenameNumbered,
+        };
+        self.transfer_with(source, destination, category, policy, config)
+    }
+
+    fn transfer_with(
+        &mut self,
+        source: PathBuf,
+        destination: PathBuf,
+        category: String,
+        policy: ConflictPolicy,
+        config: &Config,
+    ) -> io::Result<()> {
+        let compound_extensions = &config.compound_extensions;
+        let (resolution, decision) = conflict::resolve(&source, destination, policy, compound_extensions, |path| {
+            self.planned_destinations.contains(path)
+        })?;
+        self.conflicts.extend(decision);
+
+        match resolution {
+            Resolution::Transfer { destination, overwrite } => {
+                if let Some(directory) = destination.parent() {
+                    self.ensure_directory(directory);
+                }
+                self.planned_destinations.insert(destination.clone());
+                let copy = config.copy || self.outside_root.contains(&source);
+                self.operations.push(Operation {
+                    link_target: fs::read_link(&source).ok(),
+                    source,
+                    destination,
+                    mode: if copy { TransferMode::Copy } else { TransferMode::Move },
+                    overwrite,
+                    category,
+                });
+            }
+            Resolution::Skip(reason) => self.skip(source, reason),
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            SkipReason::Excluded(pattern) => return write!(f, "excluded by `{}`", pattern),
+            SkipReason::NotIncluded => "not included",
+            SkipReason::TooSmall => "smaller than the minimum size",
+            SkipReason::TooLarge => "larger than the maximum size",
+            SkipReason::TooNew => "newer than the minimum age",
+            SkipReason::TooOld => "older than the maximum age",
+            SkipReason::StaysWithPrimary => "stays with its primary file",
+            SkipReason::Project => "project directory",
+            SkipReason::Symlink => "symbolic link",
+            SkipReason::BrokenLink(target) => return write!(f, "broken link to `{}`", target.display()),
+            SkipReason::LinkedElsewhere => "organized through another path",
+            SkipReason::ProtectedLinkTarget => "link into a protected location",
+            SkipReason::LinkToParent => "link to a parent directory",
+            SkipReason::Directory => "subdirectory",
+            SkipReason::OrganizedDirectory => "organized folder",
+            SkipReason::DestinationRoot => "destination folder",
+            SkipReason::Hidden => "hidden file",
+            SkipReason::AlreadyInPlace => "already in place",
+            SkipReason::ConfigFile => "configuration file",
+            SkipReason::DestinationExists => "destination exists",
+            SkipReason::ExistingIsNewer => "existing file is newer",
+            SkipReason::ExistingIsLarger => "existing file is larger",
+            SkipReason::Duplicate => "identical file exists",
+            SkipReason::Incomplete => "still being written",
+            SkipReason::PartialDownload => "partial download",
+            SkipReason::AlreadyCopied => "already copied",
+        };
+        f.write_str(reason)
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = match self.mode {
+            TransferMode::Move => "Move",
+            TransferMode::Copy => "Copy",
+        };
+        write!(f, "{} {} -> {}", verb, self.source.display(), self.destination.display())?;
+        if self.overwrite {
+            f.write_str(" (overwrite)")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Link {} -> {}", self.path.display(), self.target.display())
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.destination == self.root {
+            writeln!(f, "Plan for {}", self.root.display())?;
+        } else {
+            writeln!(f, "Plan for {} into {}", self.root.display(), self.destination.display())?;
+        }
+        for directory in &self.directories {
+            writeln!(f, "  Create {}", directory.display())?;
+        }
+        for operation in &self.operations {
+            writeln!(f, "  {}", operation)?;
+        }
+        for link in &self.links {
+            writeln!(f, "  {}", link)?;
+        }
+        for item in &self.skipped {
+            writeln!(f, "  Skip {} ({})", item.path.display(), item.reason)?;
+        }
+        for decision in &self.conflicts {
+            writeln!(f, "  Conflict: {}", decision)?;
+        }
+        for group in &self.duplicates {
+            for duplicate in &group.duplicates {
+                writeln!(f, "  Duplicate: {} is identical to {}", duplicate.display(), group.original.display())?;
+            }
+        }
+        write!(
+            f,
+            "{} directories to create, {} files to transfer, {} items skipped.",
+            self.directories.len(),
+            self.operations.len(),
+            self.skipped.len()
+        )
+    }
+}
+
+/// Works out how the given directory would be organized, without changing anything.
+///
+/// Files with an extension are destined for the directory of the category listing
+/// that extension in `config.categories`, or else a subdirectory named after the
+/// lowercased extension; files without one go to `config.others_directory`.
+/// Destinations that are already taken are resolved with `config.conflict_policy`.
+///
+/// If `config.destination_template` is set, it decides the destination instead.
+/// The destination folders are created in `config.destination_root` if it is
+/// set, otherwise in `directory` itself.
+///
+/// Files outside the size and age limits in `config` are skipped before they
+/// are classified. Files with identical content are then handled according to
+/// `config.duplicate_policy`, and sidecar files described by `config.sidecars`
+/// go wherever their primary file goes.
+///
+/// Subdirectories are left alone unless `config.recursive` is set, in which case
+/// they are organized too, down to `config.max_depth` levels, according to
+/// `config.recursive_layout`. Project directories, recognised by
+/// `config.project_markers`, are never split up: depending on
+/// `config.project_policy` they are skipped or moved whole into
+/// `config.projects_directory`. Folders the organizer itself creates are never
+/// descended into, so running it again on an organized directory changes nothing.
+///
+/// # Arguments
+///
+/// * `directory` - The directory containing files to organize.
+/// * `config` - A reference to a `Config` structure with operation settings.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the directory or a conflicting file cannot be
+/// read. Returns one of kind `PermissionDenied` if the directory or the
+/// destination root is a protected location such as `/`, a system directory or
+/// the home directory (unless `config.allow_protected_directories` is set), if
+/// the directory is a project (unless `config.allow_project_root` is set), or if
+/// a destination would lie outside the destination directory.
+pub fn plan_organisation(directory: &str, config: &Config) -> std::io::Result<Plan> {
+    plan_with(directory, config, &CancellationToken::new(), &|_| None)
+}
+
+/// Like [`plan_organisation`], but gives up with an `Interrupted` error as soon
+/// as `cancel` is cancelled, and skips every file for which `hold` gives a reason.
+pub(crate) fn plan_with(
+    directory: &str,
+    config: &Config,
+    cancel: &CancellationToken,
+    hold: &dyn Fn(&Path) -> Option<SkipReason>,
+) -> io::Result<Plan> {
+    let root = Path::new(directory);
+    // A destination root that is the scanned directory itself changes nothing.
+    let destination = match &config.destination_root {
+        Some(destination) if !same_directory(Path::new(destination), root) => Path::new(destination),
+        _ => root,
+    };
+    safety::check_directory(root, config)?;
+    safety::check_project(root, config)?;
+    if destination != root {
+        safety::check_directory(destination, config)?;
+    }
+
+    let mut planner = Planner {
+        plan: Plan::new(root, destination),
+        root: fs::canonicalize(root)?,
+        config,
+        journal_directory: fs::canonicalize(&config.journal_directory).ok(),
+        destination_root: (destination != root).then(|| fs::canonicalize(destination).ok()).flatten(),
+        cancel,
+        hold,
+        filter: Filter::new(root, config)?,
+        files: Vec::new(),
+        all_files: Vec::new(),
+        visited: HashSet::new(),
+        followed: HashMap::new(),
+    };
+    if config.symlink_policy == SymlinkPolicy::Follow {
+        planner.visited.insert(planner.root.clone());
+    }
+
+    planner.plan_directory(root, destination, 0)?;
+    planner.plan_files()?;
+    safety::confine(&planner.plan)?;
+    Ok(planner.plan)
+}
+
+/// State shared while walking the directory tree.
+struct Planner<'a> {
+    plan: Plan,
+    /// The organized directory, with every link in its path resolved.
+    root: PathBuf,
+    config: &'a Config,
+    /// The journal directory, which is never organized even if it lies inside the tree.
+    journal_directory: Option<PathBuf>,
+    /// The separate destination root, if it exists. Like the journal directory,
+    /// it is never organized even if it lies inside the tree.
+    destination_root: Option<PathBuf>,
+    cancel: &'a CancellationToken,
+    /// Decides which files must be left alone for now, e.g. while being written.
+    hold: &'a dyn Fn(&Path) -> Option<SkipReason>,
+    /// The include and exclude patterns.
+    filter: Filter,
+    /// Files to organize, each with the directory its folders are created in,
+    /// collected while walking the tree.
+    files: Vec<(PathBuf, PathBuf)>,
+    /// Every file found while walking the tree, including those left alone,
+    /// which sidecar files may belong to.
+    all_files: Vec<PathBuf>,
+    /// With the `follow` symlink policy, the resolved directories walked so
+    /// far, so that no directory is organized twice and link loops end.
+    visited: HashSet<PathBuf>,
+    /// With the `follow` symlink policy, the link each followed file was found through.
+    followed: HashMap<PathBuf, PathBuf>,
+}
+
+impl Planner<'_> {
+    /// Plans every item directly inside `directory`, filing files into folders under `target`.
+    fn plan_directory(&mut self, directory: &Path, target: &Path, depth: usize) -> io::Result<()> {
+        let config = self.config;
+
+        for item in fs::read_dir(directory)? {
+            if self.cancel.is_cancelled() {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "operation cancelled"));
+            }
+            let item = item?;
+            let path = item.path();
+            let file_name = item.file_name();
+            let hidden = file_name.to_string_lossy().starts_with('.');
+            let file_type = item.file_type()?;
+
+            if file_type.is_symlink() {
+                // Following the link fails if nothing is at the other end.
+                if fs::metadata(&path).is_err() {
+                    let target = fs::read_link(&path)?;
+                    self.plan.skip(path, SkipReason::BrokenLink(target));
+                    continue;
+                }
+                if config.symlink_policy == SymlinkPolicy::Skip {
+                    self.plan.skip(path, SkipReason::Symlink);
+                    continue;
+                }
+            }
+            // A followed link stands for what it points to; anything else for itself.
+            let follow = file_type.is_symlink() && config.symlink_policy == SymlinkPolicy::Follow;
+            let resolved = if follow { fs::canonicalize(&path)? } else { path.clone() };
+
+            // A moved link to a directory is organized like a file.
+            if path.is_dir() && !(file_type.is_symlink() && config.symlink_policy == SymlinkPolicy::MoveLink) {
+                let descend = config.recursive
+                    && (file_type.is_dir() || follow)
+                    && config.max_depth.is_none_or(|max_depth| depth < max_depth);
+
+                if !descend {
+                    self.plan.skip(path, SkipReason::Directory);
+                } else if !config.include_hidden && hidden {
+                    self.plan.skip(path, SkipReason::Hidden);
+                } else if let Some(reason) = self.filter.check(&path, true) {
+                    self.plan.skip(path, reason);
+                } else if directory == target && self.is_organized_directory(&path)? {
+                    self.plan.skip(path, SkipReason::OrganizedDirectory);
+                } else if self.destination_root.is_some() && self.destination_root == fs::canonicalize(&path).ok() {
+                    self.plan.skip(path, SkipReason::DestinationRoot);
+                } else if follow && safety::is_protected(&resolved, config)? {
+                    self.plan.skip(path, SkipReason::ProtectedLinkTarget);
+                } else if follow && self.root.starts_with(&resolved) {
+                    self.plan.skip(path, SkipReason::LinkToParent);
+                } else if config.symlink_policy == SymlinkPolicy::Follow
+                    && !self.visited.insert(fs::canonicalize(&resolved)?)
+                {
+                    self.plan.skip(path, SkipReason::LinkedElsewhere);
+                } else if is_project(&resolved, &config.project_markers) {
+                    match config.project_policy {
+                        ProjectPolicy::Skip => self.plan.skip(path, SkipReason::Project),
+                        ProjectPolicy::Move => {
+                            let projects = target.join(&config.projects_directory);
+                            if path.parent() == Some(projects.as_path()) {
+                                self.plan.skip(path, SkipReason::AlreadyInPlace);
+                            } else {
+                                let destination = projects.join(&file_name);
+                                let category = config.projects_directory.clone();
+                                self.mark_if_outside_root(&resolved)?;
+                                self.plan.transfer_directory(resolved, destination, category, config)?;
+                            }
+                        }
+                    }
+                } else {
+                    let target = match config.recursive_layout {
+                        RecursiveLayout::Flatten => target.to_path_buf(),
+                        RecursiveLayout::InPlace => target.join(&file_name),
+                    };
+                    self.plan_directory(&resolved, &target, depth + 1)?;
+                }
+                continue;
+            }
+
+            self.all_files.push(resolved.clone());
+
+            // The directory's own settings and ignore files stay where they are.
+            if file_name == DIRECTORY_CONFIG_FILE || file_name == IGNORE_FILE {
+                self.plan.skip(path, SkipReason::ConfigFile);
+                continue;
+            }
+
+            // Skip hidden files if not included.
+            if !config.include_hidden && hidden {
+                self.plan.skip(path, SkipReason::Hidden);
+                continue;
+            }
+
+            if let Some(reason) = self.filter.check(&path, false).or_else(|| (self.hold)(&path)) {
+                self.plan.skip(path, reason);
+                continue;
+            }
+            if let Some(reason) = self.filter.check_size_and_age(&path)? {
+                self.plan.skip(path, reason);
+                continue;
+            }
+
+            if follow {
+                if resolved.parent().map_or(Ok(true), |parent| safety::is_protected(parent, config))? {
+                    self.plan.skip(path, SkipReason::ProtectedLinkTarget);
+                    continue;
+                }
+                if self.followed.contains_key(&resolved) {
+                    self.plan.skip(path, SkipReason::LinkedElsewhere);
+                    continue;
+                }
+                self.followed.insert(resolved.clone(), path);
+            }
+            self.mark_if_outside_root(&resolved)?;
+            self.files.push((resolved, target.to_path_buf()));
+        }
+
+        Ok(())
+    }
+
+    /// With the `follow` symlink policy, records that `path` is to be copied
+    /// rather than moved if it resolves to somewhere outside the organized
+    /// directory, either itself or through a followed directory it is in.
+    fn mark_if_outside_root(&mut self, path: &Path) -> io::Result<()> {
+        if self.config.symlink_policy == SymlinkPolicy::Follow && !fs::canonicalize(path)?.starts_with(&self.root) {
+            self.plan.outside_root.insert(path.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Plans the transfer of every file collected by [`Self::plan_directory`],
+    /// first grouping identical files if `config.duplicate_policy` asks for it.
+    /// Sidecar files are planned last, so that they can follow their primary file.
+    fn plan_files(&mut self) -> io::Result<()> {
+        let config = self.config;
+        let mut files = std::mem::take(&mut self.files);
+        if !self.followed.is_empty() {
+            self.drop_files_organized_elsewhere(&mut files);
+        }
+        let paths: Vec<PathBuf> = files.iter().map(|(path, _)| path.clone()).collect();
+
+        if config.duplicate_policy != DuplicatePolicy::Ignore {
+            // A moved link shares its content with its target, but is no duplicate.
+            let regular: Vec<PathBuf> = paths.iter().filter(|path| !path.is_symlink()).cloned().collect();
+            self.plan.duplicates = find_duplicates(&regular, self.cancel)?;
+        }
+        let duplicates: HashSet<PathBuf> =
+            self.plan.duplicates.iter().flat_map(|group| group.duplicates.iter().cloned()).collect();
+        let primaries = find_primaries(&paths, &self.all_files, &config.sidecars, &config.compound_extensions);
+        let (sidecars, files): (Vec<_>, Vec<_>) = files.into_iter().partition(|(path, _)| primaries.contains_key(path));
+
+        for (path, target) in files {
+            if self.cancel.is_cancelled() {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "operation cancelled"));
+            }
+
+            let (destination_directory, category) =
+                if config.duplicate_policy == DuplicatePolicy::Move && duplicates.contains(&path) {
+                    (target.join(&config.duplicates_directory), config.duplicates_directory.clone())
+                } else {
+                    destination_directory(&path, &target, config)?
+                };
+
+            // Skip if the file is already in the correct directory.
+            if path.parent() == Some(destination_directory.as_path()) {
+                self.plan.skip(path, SkipReason::AlreadyInPlace);
+                continue;
+            }
+
+            let file_name = path.file_name().unwrap_or_default().to_owned();
+            self.plan.transfer(path, destination_directory.join(file_name), category, config)?;
+        }
+
+        let transfers: HashMap<PathBuf, (PathBuf, String)> = self
+            .plan
+            .operations
+            .iter()
+            .map(|operation| (operation.source.clone(), (operation.destination.clone(), operation.category.clone())))
+            .collect();
+        for (path, _) in sidecars {
+            if self.cancel.is_cancelled() {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "operation cancelled"));
+            }
+
+            let primary = &primaries[&path];
+            match transfers.get(primary) {
+                Some((destination, category)) => {
+                    let destination = sidecar_destination(&path, primary, destination, config);
+                    self.plan.transfer(path, destination, category.clone(), config)?;
+                }
+                None => self.plan.skip(path, SkipReason::StaysWithPrimary),
+            }
+        }
+
+        if config.duplicate_policy == DuplicatePolicy::HardLink {
+            self.plan_links();
+        }
+        Ok(())
+    }
+
+    /// Removes followed files from `files` that are also found directly,
+    /// skipping the links they were followed through.
+    fn drop_files_organized_elsewhere(&mut self, files: &mut Vec<(PathBuf, PathBuf)>) {
+        let direct: HashSet<PathBuf> = files
+            .iter()
+            .filter(|(path, _)| !self.followed.contains_key(path))
+            .filter_map(|(path, _)| fs::canonicalize(path).ok())
+            .collect();
+        let mut seen = HashSet::new();
+        files.retain(|(path, _)| match self.followed.get(path) {
+            Some(link) if direct.contains(path) || !seen.insert(path.clone()) => {
+                self.plan.skip(link.clone(), SkipReason::LinkedElsewhere);
+                false
+            }
+            _ => true,
+        });
+    }
+
+    /// Plans hard links replacing each duplicate, where it ends up, with the
+    /// original, where it ends up. In copy mode only the copies are linked, so
+    /// the files being organized are never changed.
+    fn plan_links(&mut self) {
+        let destinations: HashMap<&Path, &Path> = self
+            .plan
+            .operations
+            .iter()
+            .map(|operation| (operation.source.as_path(), operation.destination.as_path()))
+            .collect();
+        let organized = |path: &Path| match destinations.get(path) {
+            Some(destination) => Some(destination.to_path_buf()),
+            None => (!self.config.copy).then(|| path.to_path_buf()),
+        };
+
+        let mut links = Vec::new();
+        for group in &self.plan.duplicates {
+            let Some(target) = organized(&group.original) else {
+                continue;
+            };
+            for duplicate in &group.duplicates {
+                if let Some(path) = organized(duplicate) {
+                    links.push(Link { path, target: target.clone() });
+                }
+            }
+        }
+        self.plan.links = links;
+    }
+
+    /// Returns `true` if `path` looks like a folder created by organizing its parent:
+    /// a category folder, the "others" folder, the duplicates or projects folder,
+    /// a folder matching the destination template or date layout, a folder
+    /// holding only files with the extension it is named after, or the journal
+    /// directory.
+    fn is_organized_directory(&self, path: &Path) -> io::Result<bool> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name == self.config.others_directory || self.config.categories.iter().any(|c| c.name == name) {
+            return Ok(true);
+        }
+        if self.config.duplicate_policy == DuplicatePolicy::Move && name == self.config.duplicates_directory {
+            return Ok(true);
+        }
+        if self.config.project_policy == ProjectPolicy::Move && name == self.config.projects_directory {
+            return Ok(true);
+        }
+        let is_category = |name: &str| {
+            name == self.config.others_directory || self.config.categories.iter().any(|c| c.name == name)
+        };
+        let template = match (&self.config.destination_template, &self.config.date_layout) {
+            (Some(template), _) => Some(template),
+            (None, Some(layout)) if !layout.with_category => Some(&layout.pattern),
+            _ => None,
+        };
+        if template.is_some_and(|template| template.matches_first_component(&name, &is_category)) {
+            return Ok(true);
+        }
+        if self.journal_directory.is_some() && fs::canonicalize(path).ok() == self.journal_directory {
+            return Ok(true);
+        }
+
+        let mut has_files = false;
+        for item in fs::read_dir(path)? {
+            let item = item?;
+            let extension = extension_of(&item.path(), &self.config.compound_extensions);
+            if !item.file_type()?.is_file() || extension.as_deref() != Some(name.as_ref()) {
+                return Ok(false);
+            }
+            has_files = true;
+        }
+        Ok(has_files)
+    }
+}
+
+/// Works out which folder under `target` the file at `path` belongs in.
+///
+/// Files with extensions go to their category's directory, or to a directory
+/// named after the extension if no category lists it. Compound extensions such
+/// as `tar.gz` count as one extension. Everything else goes to
+/// the "others" directory. Depending on `config.content_detection`, the file's
+/// content may decide its type instead of its extension. With a date layout,
+/// the file goes to date folders instead, optionally inside its category folder,
+/// and a destination template overrides all of this.
+///
+/// Returns the directory together with the category the file was classified as.
+fn destination_directory(path: &Path, target: &Path, config: &Config) -> io::Result<(PathBuf, String)> {
+    let extension = extension_of(path, &config.compound_extensions);
+    let extension = detect(path, extension, config.content_detection);
+
+    let category = match &extension {
+        Some(extension) => category_for(extension, &config.categories).unwrap_or(extension).to_owned(),
+        None => config.others_directory.clone(),
+    };
+
+    let template = match (&config.destination_template, &config.date_layout) {
+        (Some(template), _) => template,
+        (None, Some(layout)) => &layout.pattern,
+        (None, None) => return Ok((target.join(&category), category)),
+    };
+
+    let date_source = config.date_layout.as_ref().map_or(DateSource::Modified, |layout| layout.source);
+    let parent = match path.parent().and_then(Path::file_name) {
+        Some(parent) => parent.to_owned(),
+        None => fs::canonicalize(path)?.parent().and_then(Path::file_name).unwrap_or_default().to_owned(),
+    };
+    let values = TemplateValues {
+        category: &category,
+        extension: extension.as_deref().unwrap_or(&config.others_directory),
+        date: file_date(path, date_source)?,
+        size: fs::metadata(path)?.len(),
+        file_name: &path.file_name().unwrap_or_default().to_string_lossy(),
+        parent: &parent.to_string_lossy(),
+    };
+    let folders = template.render(&values);
+
+    let directory = match &config.date_layout {
+        Some(layout) if config.destination_template.is_none() && layout.with_category => {
+            target.join(&category).join(folders)
+        }
+        _ => target.join(folders),
+    };
+    Ok((directory, category))
+}
+
+/// Works out where the sidecar at `path` goes when its `primary` file goes to
+/// `destination`: the same directory, renamed to match if the primary file is
+/// renamed, e.g. `IMG_0001 (1).xmp` next to `IMG_0001 (1).CR2`.
+fn sidecar_destination(path: &Path, primary: &Path, destination: &Path, config: &Config) -> PathBuf {
+    let compound_extensions = &config.compound_extensions;
+    let file_name = path.file_name().unwrap_or_default();
+    if primary.file_name() == destination.file_name() {
+        return destination.with_file_name(file_name);
+    }
+
+    let primary_name = primary.file_name().unwrap_or_default().to_string_lossy();
+    let destination_name = destination.file_name().unwrap_or_default().to_string_lossy();
+    let (old_stem, _) = split_extension(&primary_name, compound_extensions);
+    let (new_stem, _) = split_extension(&destination_name, compound_extensions);
+    // The sidecar's name starts with the primary file's stem, see `find_primaries`.
+    let file_name = file_name.to_string_lossy();
+    destination.with_file_name(format!("{}{}", new_stem, &file_name[old_stem.len()..]))
+}
+
+/// Carries out a plan produced by [`plan_organisation`], showing a progress
+/// bar on the terminal.
+///
+/// This is [`apply_plan_with`] using a [`TerminalProgress`] observer.
+///
+/// # Errors
+///
+/// See [`apply_plan_with`].
+pub fn apply_plan(plan: &Plan, config: &Config) -> std::io::Result<RunReport> {
+    apply_plan_with(plan, config, &mut TerminalProgress::new(), &CancellationToken::new())
+}
+
+/// Carries out a plan produced by [`plan_organisation`], reporting progress to
+/// `observer`.
+///
+/// Directories are created first, then every operation is applied in order.
+/// Progress and timing information is logged to the log file named in `config`,
+/// and every change is recorded in a journal so the run can later be undone
+/// with [`undo_run`](crate::undo_run).
+///
+/// # Arguments
+///
+/// * `plan` - The plan to apply.
+/// * `config` - A reference to a `Config` structure with operation settings.
+/// * `observer` - Notified as each item is processed.
+/// * `cancel` - Checked before each file. Once cancelled, the remaining files
+///   are left alone and the report describes the work completed so far.
+///
+/// Returns a report of the run, including the id that identifies its journal.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if an error occurs during file system operations.
+/// With `config.continue_on_error`, failures to create a directory or transfer a
+/// file are recorded in the report instead, and only errors writing the log or
+/// journal end the run.
+pub fn apply_plan_with(
+    plan: &Plan,
+    config: &Config,
+    observer: &mut dyn ProgressObserver,
+    cancel: &CancellationToken,
+) -> std::io::Result<RunReport> {
+    // Start measuring total operation time.
+    let start_time = Instant::now();
+
+    // Open or create the log file.
+    let mut log_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&config.log_file)?;
+    let mut journal = JournalWriter::create(config, &plan.root)?;
+
+    observer.started(plan.item_count());
+
+    for item in &plan.skipped {
+        writeln!(log_file, "Skipping {}: {}", item.reason, item.path.display())?;
+        observer.item_skipped(&item.path, &item.reason);
+    }
+
+    for decision in &plan.conflicts {
+        writeln!(log_file, "Conflict: {}", decision)?;
+    }
+
+    let mut operations = Vec::new();
+    let mut files_by_category = BTreeMap::new();
+    let mut bytes_moved = 0;
+    let mut bytes_copied = 0;
+    let mut failures = Vec::new();
+    // Records a failure and carries on if configured to, otherwise ends the run.
+    let mut fail = |path: &Path, err: io::Error, log_file: &mut File| -> io::Result<FailedItem> {
+        if !config.continue_on_error {
+            return Err(err);
+        }
+        writeln!(log_file, "Failed: {}: {}", path.display(), err)?;
+        let failure = FailedItem::new(path.to_path_buf(), &err);
+        failures.push(failure.clone());
+        Ok(failure)
+    };
+
+    for directory in &plan.directories {
+        if let Err(err) = create_directory(directory, &mut journal) {
+            fail(directory, err, &mut log_file)?;
+        }
+    }
+
+    let mut cancelled = false;
+    for operation in &plan.operations {
+        // Stop between files, so no transfer is left half done.
+        if cancel.is_cancelled() {
+            cancelled = true;
+            break;
+        }
+
+        // Measure time for the copy/move operation.
+        let start_file_time = Instant::now();
+
+        let (verb, size) = match transfer(operation, config) {
+            Ok(transferred) => transferred,
+            Err(err) => {
+                let failure = fail(&operation.source, err, &mut log_file)?;
+                observer.item_failed(&failure);
+                continue;
+            }
+        };
+        let elapsed_time = start_file_time.elapsed();
+        journal.record(&JournalEntry::Transferred(operation.clone()))?;
+        writeln!(
+            log_file,
+            "{} {} to {} in {:.2?}",
+            verb,
+            operation.source.file_name().unwrap_or_default().to_string_lossy(),
+            operation.destination.parent().unwrap_or(&plan.root).display(),
+            elapsed_time
+        )?;
+        operations.push(operation.clone());
+        *files_by_category.entry(operation.category.clone()).or_default() += 1;
+        match operation.mode {
+            TransferMode::Move => bytes_moved += size,
+            TransferMode::Copy => bytes_copied += size,
+        }
+        observer.item_processed(&operation.source, size);
+    }
+
+    // Duplicates are linked only once both files are in their final place.
+    let mut links = Vec::new();
+    for link in &plan.links {
+        if cancel.is_cancelled() {
+            cancelled = true;
+            break;
+        }
+        if let Err(err) = replace_with_hard_link(&link.path, &link.target) {
+            fail(&link.path, err, &mut log_file)?;
+            continue;
+        }
+        journal.record(&JournalEntry::Linked(link.clone()))?;
+        writeln!(log_file, "Linked {} to {}", link.path.display(), link.target.display())?;
+        links.push(link.clone());
+    }
+
+    let total_elapsed_time = start_time.elapsed();
+    let current_time = Local::now();
+    let status = if cancelled {
+        format!("after cancellation, with {} of {} files transferred,", operations.len(), plan.operations.len())
+    } else if failures.is_empty() {
+        "successfully".to_owned()
+    } else {
+        format!("with {} failures", failures.len())
+    };
+    writeln!(
+        log_file,
+        "Operation completed {} at {} on {}. Total time taken: {:.2?}, {:?} files processed. Run id: {}\n",
+        status,
+        current_time.format("%H:%M:%S"),
+        current_time.format("%A, %B %d, %Y"),
+        total_elapsed_time,
+        plan.item_count(),
+        journal.run_id(),
+    )?;
+
+    let report = RunReport {
+        run_id: journal.run_id().to_owned(),
+        root: plan.root.clone(),
+        destination: plan.destination.clone(),
+        elapsed: total_elapsed_time,
+        operations,
+        files_by_category,
+        bytes_moved,
+        bytes_copied,
+        skipped: plan.skipped.iter().filter(|item| !item.reason.is_size_or_age()).cloned().collect(),
+        filtered: plan.skipped.iter().filter(|item| item.reason.is_size_or_age()).cloned().collect(),
+        duplicates: plan.duplicates.clone(),
+        links,
+        failures,
+        cancelled,
+    };
+    observer.finished(&report);
+    Ok(report)
+}
+
+/// Transfers a single file or project directory as described by `operation`,
+/// returning the verb to log and the size of the file or directory.
+fn transfer(operation: &Operation, config: &Config) -> io::Result<(&'static str, u64)> {
+    // Never replace a file unless the plan explicitly says so; the
+    // destination may have been created since the plan was made.
+    let replace = operation.destination.symlink_metadata().is_ok();
+    if replace && !operation.overwrite {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", operation.destination.display()),
+        ));
+    }
+
+    let is_link = operation.source.is_symlink();
+    let is_directory = !is_link && operation.source.is_dir();
+    let size = if is_directory {
+        directory_size(&operation.source)?
+    } else {
+        fs::symlink_metadata(&operation.source)?.len()
+    };
+    let verb = match operation.mode {
+        // The existing file is only replaced once the new content is complete.
+        TransferMode::Copy if replace => {
+            copy_replacing(&operation.source, &operation.destination, config.verify_transfers)?;
+            if is_link { "Copied link" } else { "Copied" }
+        }
+        TransferMode::Copy if is_link => {
+            relink(&operation.source, &operation.destination)?;
+            "Copied link"
+        }
+        TransferMode::Copy if is_directory => {
+            copy_directory(&operation.source, &operation.destination, config.verify_transfers)?;
+            "Copied"
+        }
+        TransferMode::Copy => {
+            copy_file(&operation.source, &operation.destination, config.verify_transfers)?;
+            "Copied"
+        }
+        TransferMode::Move => {
+            let transfer = if replace {
+                move_replacing(&operation.source, &operation.destination, config.verify_transfers)?
+            } else {
+                move_file(&operation.source, &operation.destination, config.verify_transfers)?
+            };
+            match transfer {
+                Transfer::Renamed => "Moved",
+                Transfer::Copied => "Moved (across file systems)",
+                Transfer::Relinked => "Moved link",
+            }
+        }
+    };
+    Ok((verb, size))
+}
+
+/// Whether `a` and `b` name the same existing directory.
+fn same_directory(a: &Path, b: &Path) -> bool {
+    matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Creates `directory` and any missing parents, journaling each one created.
+fn create_directory(directory: &Path, journal: &mut JournalWriter) -> std::io::Result<()> {
+    let missing: Vec<&Path> = directory
+        .ancestors()
+        .take_while(|d| !d.as_os_str().is_empty() && !d.exists())
+        .collect();
+
+    for directory in missing.into_iter().rev() {
+        fs::create_dir(directory)?;
+        journal.record(&JournalEntry::CreatedDirectory(directory.to_path_buf()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_name_ending_in_a_dot_is_organized_inside_the_root() {
+        let scratch = tempfile::tempdir().unwrap();
+        let root = scratch.path();
+        fs::write(root.join("weird."), "").unwrap();
+
+        let config = Config { destination_template: Some("{category}/{ext}".parse().unwrap()), ..Config::default() };
+        let plan = plan_organisation(&root.to_string_lossy(), &config).unwrap();
+        let destinations: Vec<_> = plan.operations.iter().map(|operation| operation.destination.clone()).collect();
+        assert_eq!(destinations, [root.canonicalize().unwrap().join("Others/Others/weird.")]);
+    }
+
+    #[test]
+    fn duplicates_are_moved_aside_or_linked() {
+        let scratch = tempfile::tempdir().unwrap();
+        let root = scratch.path().canonicalize().unwrap();
+        fs::write(root.join("report.pdf"), "report").unwrap();
+        fs::write(root.join("report (1).pdf"), "report").unwrap();
+
+        let config = Config { duplicate_policy: DuplicatePolicy::Move, ..Config::default() };
+        let plan = plan_organisation(&root.to_string_lossy(), &config).unwrap();
+        let mut destinations: Vec<_> = plan.operations.iter().map(|operation| operation.destination.clone()).collect();
+        destinations.sort();
+        assert_eq!(destinations, [root.join("Documents/report.pdf"), root.join("Duplicates/report (1).pdf")]);
+
+        let config = Config { duplicate_policy: DuplicatePolicy::HardLink, ..Config::default() };
+        let plan = plan_organisation(&root.to_string_lossy(), &config).unwrap();
+        assert_eq!(plan.links.len(), 1);
+        assert_eq!(plan.links[0].path, root.join("Documents/report (1).pdf"));
+        assert_eq!(plan.links[0].target, root.join("Documents/report.pdf"));
+    }
+
+    #[test]
+    fn sidecars_follow_their_primary_file() {
+        let scratch = tempfile::tempdir().unwrap();
+        let root = scratch.path().canonicalize().unwrap();
+        fs::create_dir(root.join("Images")).unwrap();
+        fs::write(root.join("Images/IMG_1.cr2"), "earlier").unwrap();
+        fs::write(root.join("IMG_1.cr2"), "raw").unwrap();
+        fs::write(root.join("IMG_1.xmp"), "edits").unwrap();
+        fs::write(root.join("IMG_2.cr2"), "a much larger raw file").unwrap();
+        fs::write(root.join("IMG_2.xmp"), "edits").unwrap();
+
+        let config = Config { max_size: Some(10), ..Config::default() };
+        let plan = plan_organisation(&root.to_string_lossy(), &config).unwrap();
+        let mut transfers: Vec<_> = plan
+            .operations
+            .iter()
+            .map(|operation| (operation.source.clone(), operation.destination.clone()))
+            .collect();
+        transfers.sort();
+        assert_eq!(
+            transfers,
+            [
+                (root.join("IMG_1.cr2"), root.join("Images/IMG_1 (1).cr2")),
+                (root.join("IMG_1.xmp"), root.join("Images/IMG_1 (1).xmp")),
+            ]
+        );
+        let reason = |name: &str| plan.skipped.iter().find(|item| item.path == root.join(name)).map(|item| &item.reason);
+        assert_eq!(reason("IMG_2.cr2"), Some(&SkipReason::TooLarge));
+        assert_eq!(reason("IMG_2.xmp"), Some(&SkipReason::StaysWithPrimary));
+    }
+
+    /// Planned sources with their destinations, and skipped items with their reasons.
+    #[cfg(unix)]
+    type Outline = (Vec<(PathBuf, PathBuf)>, Vec<(PathBuf, SkipReason)>);
+
+    /// Plans `root` with `policy`, giving the outline of the plan relative to `root`.
+    #[cfg(unix)]
+    fn plan_links(root: &Path, policy: SymlinkPolicy) -> Outline {
+        let config = Config { symlink_policy: policy, recursive: true, ..Config::default() };
+        let plan = plan_organisation(&root.to_string_lossy(), &config).unwrap();
+        let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+        let mut operations: Vec<_> = plan
+            .operations
+            .iter()
+            .map(|operation| (relative(&operation.source), relative(&operation.destination)))
+            .collect();
+        let mut skipped: Vec<_> = plan.skipped.iter().map(|item| (relative(&item.path), item.reason.clone())).collect();
+        operations.sort();
+        skipped.sort();
+        (operations, skipped)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_are_skipped_moved_or_followed() {
+        use std::os::unix::fs::symlink;
+
+        let scratch = tempfile::tempdir().unwrap();
+        let root = scratch.path().canonicalize().unwrap();
+        fs::create_dir(root.join("folder")).unwrap();
+        fs::write(root.join("folder/notes.txt"), "notes").unwrap();
+        symlink("folder/notes.txt", root.join("shortcut.txt")).unwrap();
+        symlink("missing.txt", root.join("broken.txt")).unwrap();
+        let broken = || (PathBuf::from("broken.txt"), SkipReason::BrokenLink(PathBuf::from("missing.txt")));
+        let notes = || (PathBuf::from("folder/notes.txt"), PathBuf::from("Documents/notes.txt"));
+
+        let (operations, skipped) = plan_links(&root, SymlinkPolicy::Skip);
+        assert_eq!(operations, [notes()]);
+        assert_eq!(skipped, [broken(), (PathBuf::from("shortcut.txt"), SkipReason::Symlink)]);
+
+        let (operations, skipped) = plan_links(&root, SymlinkPolicy::MoveLink);
+        let link = (PathBuf::from("shortcut.txt"), PathBuf::from("Documents/shortcut.txt"));
+        assert_eq!(operations, [notes(), link]);
+        assert_eq!(skipped, [broken()]);
+
+        // The file is found directly too, and only organized once.
+        let (operations, skipped) = plan_links(&root, SymlinkPolicy::Follow);
+        assert_eq!(operations, [notes()]);
+        assert_eq!(skipped, [broken(), (PathBuf::from("shortcut.txt"), SkipReason::LinkedElsewhere)]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn followed_links_never_loop() {
+        use std::os::unix::fs::symlink;
+
+        let scratch = tempfile::tempdir().unwrap();
+        let root = scratch.path().join("inbox");
+        fs::create_dir_all(root.join("folder")).unwrap();
+        fs::write(root.join("folder/notes.txt"), "notes").unwrap();
+        symlink("..", root.join("folder/up")).unwrap();
+        symlink(scratch.path(), root.join("parent")).unwrap();
+        let root = root.canonicalize().unwrap();
+
+        let (operations, skipped) = plan_links(&root, SymlinkPolicy::Follow);
+        assert_eq!(operations, [(PathBuf::from("folder/notes.txt"), PathBuf::from("Documents/notes.txt"))]);
+        assert_eq!(
+            skipped,
+            [
+                (PathBuf::from("folder/up"), SkipReason::LinkToParent),
+                (PathBuf::from("parent"), SkipReason::LinkToParent),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn followed_links_leaving_the_root_are_copied() {
+        use std::os::unix::fs::symlink;
+
+        let scratch = tempfile::tempdir().unwrap();
+        let scratch = scratch.path().canonicalize().unwrap();
+        let (root, elsewhere) = (scratch.join("shared"), scratch.join("private"));
+        fs::create_dir_all(elsewhere.join("folder")).unwrap();
+        fs::create_dir(&root).unwrap();
+        fs::write(elsewhere.join("id_rsa"), "secret").unwrap();
+        fs::write(elsewhere.join("folder/report.txt"), "report").unwrap();
+        fs::write(root.join("notes.txt"), "notes").unwrap();
+        symlink(elsewhere.join("id_rsa"), root.join("key")).unwrap();
+        symlink(elsewhere.join("folder"), root.join("folder")).unwrap();
+
+        let config = Config { symlink_policy: SymlinkPolicy::Follow, recursive: true, ..Config::default() };
+        let plan = plan_organisation(&root.to_string_lossy(), &config).unwrap();
+        let mut modes: Vec<_> = plan
+            .operations
+            .iter()
+            .map(|operation| (operation.source.strip_prefix(&scratch).unwrap().to_path_buf(), operation.mode))
+            .collect();
+        modes.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            modes,
+            [
+                (PathBuf::from("private/folder/report.txt"), TransferMode::Copy),
+                (PathBuf::from("private/id_rsa"), TransferMode::Copy),
+                (PathBuf::from("shared/notes.txt"), TransferMode::Move),
+            ]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Progress reporting while a plan is applied.
+//!
+//! [`apply_plan_with`](crate::apply_plan_with) reports each step to a
+//! [`ProgressObserver`]. [`TerminalProgress`] draws a progress bar on the
+//! terminal, and [`ChannelProgress`] forwards [`ProgressEvent`]s to another
+//! thread, such as the GUI's.
+
+use indicatif::{ProgressBar, ProgressStyle};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+
+use crate::plan::SkipReason;
+use crate::report::{FailedItem, RunReport};
+
+/// Receives progress notifications while a plan is applied.
+///
+/// Every method does nothing by default, so implementations only need to
+/// handle the notifications they care about.
+pub trait ProgressObserver {
+    /// Called once before anything is done, with the number of items in the
+    /// plan: files to transfer plus skipped items.
+    fn started(&mut self, _total: usize) {}
+
+    /// Called after the file at `path`, of `bytes` bytes, has been transferred.
+    fn item_processed(&mut self, _path: &Path, _bytes: u64) {}
+
+    /// Called for each item the plan leaves untouched.
+    fn item_skipped(&mut self, _path: &Path, _reason: &SkipReason) {}
+
+    /// Called for each file that could not be transferred. Directories that
+    /// could not be created only appear in the final report.
+    fn item_failed(&mut self, _failure: &FailedItem) {}
+
+    /// Called once when the run is complete.
+    fn finished(&mut self, _report: &RunReport) {}
+}
+
+/// An observer that ignores all notifications.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {}
+
+/// An observer that draws a progress bar on the terminal.
+#[derive(Debug, Default)]
+pub struct TerminalProgress {
+    bar: Option<ProgressBar>,
+}
+
+impl TerminalProgress {
+    /// Creates an observer whose bar appears once the run starts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ProgressObserver for TerminalProgress {
+    fn started(&mut self, total: usize) {
+        let style = ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} {msg}")
+            .unwrap()
+            .progress_chars("█▉▍ ");
+        self.bar = Some(ProgressBar::new(total as u64).with_style(style));
+    }
+
+    fn item_processed(&mut self, path: &Path, _bytes: u64) {
+        if let Some(bar) = &self.bar {
+            bar.set_message(path.file_name().unwrap_or_default().to_string_lossy().into_owned());
+            bar.inc(1);
+        }
+    }
+
+    fn item_skipped(&mut self, _path: &Path, _reason: &SkipReason) {
+        if let Some(bar) = &self.bar {
+            bar.inc(1);
+        }
+    }
+
+    fn item_failed(&mut self, _failure: &FailedItem) {
+        if let Some(bar) = &self.bar {
+            bar.inc(1);
+        }
+    }
+
+    fn finished(&mut self, _report: &RunReport) {
+        if let Some(bar) = &self.bar {
+            bar.finish_with_message("Done!");
+        }
+    }
+}
+
+/// A progress notification sent by [`ChannelProgress`].
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    /// The run started with this many items.
+    Started { total: usize },
+    /// A file was transferred.
+    ItemProcessed { path: PathBuf, bytes: u64 },
+    /// An item was left untouched.
+    ItemSkipped { path: PathBuf, reason: SkipReason },
+    /// A file could not be transferred.
+    ItemFailed(FailedItem),
+    /// The run is complete.
+    Finished,
+}
+
+/// An observer that sends every notification over a channel, so that another
+/// thread can display progress.
+///
+/// Sending stops silently once the receiver is gone.
+#[derive(Debug, Clone)]
+pub struct ChannelProgress {
+    sender: Sender<ProgressEvent>,
+}
+
+impl ChannelProgress {
+    /// Creates an observer sending events to `sender`.
+    pub fn new(sender: Sender<ProgressEvent>) -> Self {
+        Self { sender }
+    }
+
+    fn send(&self, event: ProgressEvent) {
+        let _ = self.sender.send(event);
+    }
+}
+
+impl ProgressObserver for ChannelProgress {
+    fn started(&mut self, total: usize) {
+        self.send(ProgressEvent::Started { total });
+    }
+
+    fn item_processed(&mut self, path: &Path, bytes: u64) {
+        self.send(ProgressEvent::ItemProcessed { path: path.to_path_buf(), bytes });
+    }
+
+    fn item_skipped(&mut self, path: &Path, reason: &SkipReason) {
+        self.send(ProgressEvent::ItemSkipped { path: path.to_path_buf(), reason: reason.clone() });
+    }
+
+    fn item_failed(&mut self, failure: &FailedItem) {
+        self.send(ProgressEvent::ItemFailed(failure.clone()));
+    }
+
+    fn finished(&mut self, _report: &RunReport) {
+        self.send(ProgressEvent::Finished);
+    }
+}
@@ -0,0 +1,77 @@
+//! Project directories that are kept in one piece.
+//!
+//! A git checkout or a Cargo or npm project only works as a whole, so when
+//! organizing recursively, a directory containing one of the marker files or
+//! directories in `Config::project_markers` is never descended into. Instead,
+//! [`ProjectPolicy`] decides whether it is left alone or moved whole into the
+//! projects folder.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
+
+/// What to do with a project directory found while organizing recursively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectPolicy {
+    /// The project is left where it is.
+    #[default]
+    Skip,
+    /// The project is moved, as a whole, into the projects folder.
+    Move,
+}
+
+impl ProjectPolicy {
+    /// Every policy, in the order they are offered to users.
+    pub const ALL: [ProjectPolicy; 2] = [ProjectPolicy::Skip, ProjectPolicy::Move];
+
+    /// The name used for the policy in configuration files and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProjectPolicy::Skip => "skip",
+            ProjectPolicy::Move => "move",
+        }
+    }
+
+    /// A short human-readable description of the policy.
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProjectPolicy::Skip => "Leave them alone",
+            ProjectPolicy::Move => "Move them whole into their own folder",
+        }
+    }
+}
+
+impl FromStr for ProjectPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ProjectPolicy::ALL
+            .into_iter()
+            .find(|policy| policy.name() == s)
+            .ok_or_else(|| format!("unknown project policy '{}', expected one of: skip, move", s))
+    }
+}
+
+/// The built-in names of files and directories that mark a project.
+pub fn default_project_markers() -> Vec<String> {
+    [
+        ".git",
+        ".hg",
+        ".svn",
+        "Cargo.toml",
+        "package.json",
+        "pyproject.toml",
+        "go.mod",
+        "pom.xml",
+        "build.gradle",
+        "CMakeLists.txt",
+    ]
+    .map(str::to_owned)
+    .to_vec()
+}
+
+/// Returns `true` if `directory` directly contains one of the `markers`.
+pub(crate) fn is_project(directory: &Path, markers: &[String]) -> bool {
+    markers.iter().any(|marker| directory.join(marker).symlink_metadata().is_ok())
+}
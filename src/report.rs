@@ -0,0 +1,160 @@
+//! Summaries of completed organize runs.
+//!
+//! [`apply_plan`](crate::apply_plan) returns a [`RunReport`] describing what the
+//! run did: the files transferred per category, the bytes moved and copied, the
+//! items skipped and why, the files filtered out by size or age, duplicate
+//! files found, and every item that could not be organized when
+//! `Config::continue_on_error` is set. It serializes to JSON for automation.
+
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::io;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::duplicate::DuplicateGroup;
+use crate::plan::{Link, Operation, SkipReason, SkippedItem};
+
+/// An item that could not be organized, together with the error that occurred.
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedItem {
+    /// Path of the file or directory that failed.
+    pub path: PathBuf,
+    /// The kind of error, such as permission denied.
+    #[serde(serialize_with = "serialize_error_kind")]
+    pub kind: io::ErrorKind,
+    /// The full error message.
+    pub message: String,
+}
+
+impl FailedItem {
+    pub(crate) fn new(path: PathBuf, error: &io::Error) -> Self {
+        Self {
+            path,
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for FailedItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+fn serialize_error_kind<S: Serializer>(kind: &io::ErrorKind, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(kind)
+}
+
+/// What an organize run did.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunReport {
+    /// The id of the journaled run, used to undo it.
+    pub run_id: String,
+    /// The directory that was organized.
+    pub root: PathBuf,
+    /// The directory the organized folders were created in.
+    pub destination: PathBuf,
+    /// How long applying the plan took.
+    pub elapsed: Duration,
+    /// The transfers that were carried out, in order.
+    pub operations: Vec<Operation>,
+    /// Number of files transferred into each category.
+    pub files_by_category: BTreeMap<String, usize>,
+    /// Total size in bytes of the files moved.
+    pub bytes_moved: u64,
+    /// Total size in bytes of the files copied.
+    pub bytes_copied: u64,
+    /// Items that were left untouched, and why.
+    pub skipped: Vec<SkippedItem>,
+    /// Files left untouched because of the size or age limits, counted
+    /// separately from the other skipped items.
+    pub filtered: Vec<SkippedItem>,
+    /// Groups of files with identical content, if duplicates were looked for.
+    pub duplicates: Vec<DuplicateGroup>,
+    /// Duplicates that were replaced with hard links.
+    pub links: Vec<Link>,
+    /// Items that could not be organized. Always empty unless
+    /// `Config::continue_on_error` is set, since the first failure otherwise
+    /// ends the run with an error.
+    pub failures: Vec<FailedItem>,
+    /// Whether the run was cancelled before every file was transferred.
+    pub cancelled: bool,
+}
+
+impl RunReport {
+    /// Returns `true` if every item was organized without error.
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty() && !self.cancelled
+    }
+
+    /// Number of skipped items for each reason.
+    pub fn skipped_by_reason(&self) -> BTreeMap<SkipReason, usize> {
+        let mut counts = BTreeMap::new();
+        for item in &self.skipped {
+            *counts.entry(item.reason.clone()).or_default() += 1;
+        }
+        counts
+    }
+}
+
+impl fmt::Display for RunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Organized {} into {} in {:.2?}. Run id: {}",
+            self.root.display(),
+            self.destination.display(),
+            self.elapsed,
+            self.run_id
+        )?;
+        writeln!(
+            f,
+            "  {} files transferred ({} moved, {} copied)",
+            self.operations.len(),
+            format_bytes(self.bytes_moved),
+            format_bytes(self.bytes_copied)
+        )?;
+        for (category, count) in &self.files_by_category {
+            writeln!(f, "    {}: {}", category, count)?;
+        }
+        writeln!(f, "  {} items skipped", self.skipped.len())?;
+        for (reason, count) in self.skipped_by_reason() {
+            writeln!(f, "    {}: {}", reason, count)?;
+        }
+        if !self.filtered.is_empty() {
+            writeln!(f, "  {} files filtered out by size or age", self.filtered.len())?;
+        }
+        if !self.duplicates.is_empty() {
+            writeln!(
+                f,
+                "  {} duplicate files ({} wasted), {} replaced with hard links",
+                self.duplicates.iter().map(|group| group.duplicates.len()).sum::<usize>(),
+                format_bytes(self.duplicates.iter().map(DuplicateGroup::wasted_bytes).sum()),
+                self.links.len()
+            )?;
+        }
+        write!(f, "  {} items failed", self.failures.len())?;
+        if self.cancelled {
+            write!(f, "\n  Cancelled before all files were transferred")?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats a byte count with a binary unit, e.g. `1.5 MiB`.
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
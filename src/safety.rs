@@ -0,0 +1,301 @@
+//! Guards against organizing places where moving files would do harm.
+//!
+//! Organizing `/`, a system directory such as `/usr/bin`, or the home directory
+//! itself would scatter files that programs and the user rely on, so
+//! [`check_directory`] refuses them unless `Config::allow_protected_directories`
+//! is set. Organizing a project would split it up, so [`check_project`]
+//! refuses that unless `Config::allow_project_root` is set. Every planned
+//! destination is also confined to the destination directory by [`confine`],
+//! so that no folder name or symbolic link can send files anywhere else.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::plan::Plan;
+use crate::project::is_project;
+use crate::Config;
+
+/// Directories that, with everything inside them, belong to the operating system.
+#[cfg(unix)]
+const SYSTEM_DIRECTORIES: &[&str] = &[
+    "/bin", "/boot", "/dev", "/etc", "/lib", "/lib32", "/lib64", "/libx32", "/proc", "/sbin", "/snap", "/sys", "/usr",
+    "/var/cache", "/var/db", "/var/empty", "/var/lib", "/var/lock", "/var/log", "/var/mail", "/var/spool",
+    "/run/dbus", "/run/lock", "/run/systemd", "/run/udev", "/System", "/Library", "/Applications",
+];
+
+/// Directories that belong to the operating system themselves, while some of
+/// the places inside them belong to users: home directories under `/var/home`,
+/// removable drives under `/run/media` and temporary directories under
+/// `/private/var/folders` on macOS.
+#[cfg(unix)]
+const SYSTEM_PARENT_DIRECTORIES: &[&str] = &["/var", "/run"];
+#[cfg(windows)]
+const SYSTEM_PARENT_DIRECTORIES: &[&str] = &[];
+
+/// Environment variables naming directories that, with everything inside
+/// them, belong to the operating system.
+#[cfg(windows)]
+const SYSTEM_DIRECTORY_VARIABLES: &[&str] = &["SystemRoot", "ProgramFiles", "ProgramFiles(x86)", "ProgramData"];
+
+/// Checks that `directory` may be organized, or have organized folders created in it.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` of kind `PermissionDenied` if `directory` is the
+/// root of a file system, a system directory or inside one, or the home
+/// directory or one of its parents, unless `config.allow_protected_directories`
+/// is set. Returns other errors if the directory cannot be resolved.
+pub(crate) fn check_directory(directory: &Path, config: &Config) -> io::Result<()> {
+    let Some(reason) = protection(directory, config)? else {
+        return Ok(());
+    };
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!(
+            "refusing to organize {}: {}; set allow_protected_directories (--force) to organize it anyway",
+            directory.display(),
+            reason
+        ),
+    ))
+}
+
+/// Checks that `directory` is not itself a project, since organizing it would
+/// split the project up. Projects found inside it are kept whole by the planner.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` of kind `PermissionDenied` if `directory`
+/// contains one of `config.project_markers`, unless `config.allow_project_root`
+/// is set.
+pub(crate) fn check_project(directory: &Path, config: &Config) -> io::Result<()> {
+    if config.allow_project_root || !is_project(directory, &config.project_markers) {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!(
+            "refusing to organize {}: it is a project; set allow_project_root (--allow-project) to organize it anyway",
+            directory.display()
+        ),
+    ))
+}
+
+/// Returns `true` if `directory` is a protected location that
+/// [`check_directory`] would refuse.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the directory cannot be resolved.
+pub(crate) fn is_protected(directory: &Path, config: &Config) -> io::Result<bool> {
+    Ok(protection(directory, config)?.is_some())
+}
+
+/// Explains why `directory` is protected, or returns `None` if it is not.
+fn protection(directory: &Path, config: &Config) -> io::Result<Option<&'static str>> {
+    if config.allow_protected_directories {
+        return Ok(None);
+    }
+    let resolved = resolve(directory)?;
+    let reason = if resolved.parent().is_none() {
+        "it is the root of the file system"
+    } else if home_directory().is_some_and(|home| home == resolved) {
+        "it is the home directory"
+    } else if home_directory().is_some_and(|home| home.starts_with(&resolved)) {
+        "it contains the home directory"
+    } else if is_system_directory(&resolved) {
+        "it is a system directory"
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(reason))
+}
+
+/// Checks that every directory to create, file destination and link in `plan`
+/// lies inside `plan.destination`, following any symbolic links among the
+/// directories that already exist.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` of kind `PermissionDenied` naming the first
+/// destination that escapes, or other errors if a path cannot be resolved.
+pub(crate) fn confine(plan: &Plan) -> io::Result<()> {
+    let root = resolve(&plan.destination)?;
+    let destinations = plan
+        .directories
+        .iter()
+        .chain(plan.operations.iter().map(|operation| &operation.destination))
+        .chain(plan.links.iter().map(|link| &link.path));
+
+    // Many files share a directory, which only needs resolving once.
+    let mut confined = HashSet::new();
+    for destination in destinations {
+        let directory = destination.parent().unwrap_or(destination);
+        let escapes = destination.components().any(|component| component == Component::ParentDir)
+            || (!confined.contains(directory) && !resolve(directory)?.starts_with(&root));
+        if escapes {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "refusing to organize: {} is outside {}",
+                    destination.display(),
+                    plan.destination.display()
+                ),
+            ));
+        }
+        confined.insert(directory);
+    }
+    Ok(())
+}
+
+/// Makes `path` absolute and resolves symbolic links in the part of it that
+/// exists. Missing trailing components are appended as they are.
+fn resolve(path: &Path) -> io::Result<PathBuf> {
+    let mut missing = Vec::new();
+    let mut existing = path;
+    let resolved = loop {
+        if existing.as_os_str().is_empty() {
+            break env::current_dir()?;
+        }
+        match fs::canonicalize(existing) {
+            Ok(canonical) => break canonical,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => match (existing.parent(), existing.file_name()) {
+                (Some(parent), Some(name)) => {
+                    missing.push(name);
+                    existing = parent;
+                }
+                _ => return Err(err),
+            },
+            Err(err) => return Err(err),
+        }
+    };
+    Ok(missing.iter().rev().fold(resolved, |path, name| path.join(name)))
+}
+
+fn home_directory() -> Option<PathBuf> {
+    dirs::home_dir().and_then(|home| fs::canonicalize(home).ok())
+}
+
+/// Returns `true` if the resolved path `directory` belongs to the operating
+/// system. Nothing in the home directory does, wherever the home directory is.
+fn is_system_directory(directory: &Path) -> bool {
+    if home_directory().is_some_and(|home| directory.starts_with(home)) {
+        return false;
+    }
+    #[cfg(unix)]
+    let directories = SYSTEM_DIRECTORIES.iter().map(PathBuf::from);
+    #[cfg(windows)]
+    let directories = SYSTEM_DIRECTORY_VARIABLES.iter().filter_map(|variable| env::var_os(variable)).map(PathBuf::from);
+    // Compare like with like, e.g. `/bin` is a link to `/usr/bin` on many systems.
+    let canonical = |directory: &Path| fs::canonicalize(directory).ok();
+    directories.filter_map(|system| canonical(&system)).any(|system| directory.starts_with(system))
+        || SYSTEM_PARENT_DIRECTORIES
+            .iter()
+            .filter_map(|parent| canonical(Path::new(parent)))
+            .any(|parent| directory == parent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{Operation, TransferMode};
+
+    fn refused(directory: &Path) -> bool {
+        match check_directory(directory, &Config::default()) {
+            Ok(()) => false,
+            Err(err) => err.kind() == io::ErrorKind::PermissionDenied,
+        }
+    }
+
+    fn move_to(destination: PathBuf) -> Operation {
+        Operation {
+            source: PathBuf::from("file.txt"),
+            destination,
+            mode: TransferMode::Move,
+            overwrite: false,
+            category: "Documents".to_owned(),
+            link_target: None,
+        }
+    }
+
+    #[test]
+    fn refuses_protected_locations() {
+        #[cfg(unix)]
+        for directory in ["/", "/usr/bin", "/etc", "/var", "/run", "/var/lib/something"] {
+            assert!(refused(Path::new(directory)), "{} should be refused", directory);
+        }
+        if let Some(home) = dirs::home_dir() {
+            assert!(refused(&home));
+            assert!(refused(home.parent().unwrap()));
+        }
+        let config = Config { allow_protected_directories: true, ..Config::default() };
+        check_directory(Path::new("/"), &config).unwrap();
+    }
+
+    #[test]
+    fn allows_ordinary_directories() {
+        let scratch = tempfile::tempdir().unwrap();
+        assert!(!refused(scratch.path()));
+        assert!(!refused(&scratch.path().join("not yet created")));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn allows_user_locations_inside_system_directories() {
+        for directory in ["/run/media/someone/Drive", "/var/home/someone/Downloads", "/var/tmp/inbox"] {
+            assert!(!refused(Path::new(directory)), "{} should be allowed", directory);
+        }
+    }
+
+    #[test]
+    fn confines_destinations_to_the_destination_directory() {
+        let scratch = tempfile::tempdir().unwrap();
+        let root = scratch.path().join("inbox");
+        fs::create_dir(&root).unwrap();
+
+        let mut plan = Plan::new(&root, &root);
+        plan.directories.push(root.join("Documents"));
+        plan.operations.push(move_to(root.join("Documents/file.txt")));
+        confine(&plan).unwrap();
+
+        let mut escaping = Plan::new(&root, &root);
+        escaping.operations.push(move_to(root.join("Documents/../../file.txt")));
+        assert_eq!(confine(&escaping).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+
+        let mut elsewhere = Plan::new(&root, &root);
+        elsewhere.directories.push(scratch.path().join("Documents"));
+        assert!(confine(&elsewhere).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn confines_destinations_through_links() {
+        let scratch = tempfile::tempdir().unwrap();
+        let root = scratch.path().join("inbox");
+        fs::create_dir_all(scratch.path().join("elsewhere")).unwrap();
+        fs::create_dir(&root).unwrap();
+        std::os::unix::fs::symlink(scratch.path().join("elsewhere"), root.join("Documents")).unwrap();
+
+        let mut plan = Plan::new(&root, &root);
+        plan.operations.push(move_to(root.join("Documents/file.txt")));
+        assert_eq!(confine(&plan).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn refuses_a_project_but_not_a_directory_inside_one() {
+        let scratch = tempfile::tempdir().unwrap();
+        let project = scratch.path();
+        fs::create_dir_all(project.join(".git")).unwrap();
+        fs::create_dir(project.join("Downloads")).unwrap();
+        let config = Config::default();
+
+        let err = check_project(project, &config).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        check_project(&project.join("Downloads"), &config).unwrap();
+        check_project(project, &Config { allow_project_root: true, ..Config::default() }).unwrap();
+        // Forcing past the protected locations is a separate decision.
+        assert!(check_project(project, &Config { allow_protected_directories: true, ..Config::default() }).is_err());
+    }
+}
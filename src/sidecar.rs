@@ -0,0 +1,170 @@
+//! Sidecar files that must stay with the file they belong to.
+//!
+//! Some files only make sense next to another one: an `.xmp` file holding the
+//! edits of a raw photo, subtitles for a video, or the `.shx` and `.dbf` parts
+//! of a shapefile. A [`SidecarRule`] names the extensions of such primary files
+//! and of their sidecars. A sidecar whose name starts with the stem of a primary
+//! file in the same directory (`IMG_0001.xmp`, `IMG_0001.CR2.xmp` or
+//! `movie.en.srt`) is moved wherever the primary file goes.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::category::{extension_of, split_extension};
+
+/// Extensions of primary files and of the sidecar files that follow them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct SidecarRule {
+    /// A description of the rule, e.g. `Raw photos`.
+    pub name: String,
+    /// Extensions (without the leading dot) of primary files. Matching is
+    /// case-insensitive.
+    pub primary_extensions: Vec<String>,
+    /// Extensions (without the leading dot) of the sidecar files that follow
+    /// a primary file. Matching is case-insensitive.
+    pub sidecar_extensions: Vec<String>,
+}
+
+impl SidecarRule {
+    fn new(name: &str, primary_extensions: &[&str], sidecar_extensions: &[&str]) -> Self {
+        Self {
+            name: name.to_owned(),
+            primary_extensions: primary_extensions.iter().map(|&extension| extension.to_owned()).collect(),
+            sidecar_extensions: sidecar_extensions.iter().map(|&extension| extension.to_owned()).collect(),
+        }
+    }
+}
+
+/// The built-in sidecar rules.
+pub fn default_sidecar_rules() -> Vec<SidecarRule> {
+    vec![
+        SidecarRule::new(
+            "Raw photos",
+            &["raw", "cr2", "cr3", "nef", "arw", "dng", "orf", "rw2", "jpg", "jpeg", "tif", "tiff", "heic"],
+            &["xmp"],
+        ),
+        SidecarRule::new(
+            "Video subtitles",
+            &["mkv", "mp4", "avi", "mov", "wmv", "webm", "m4v", "mpg", "mpeg"],
+            &["srt", "ass", "ssa", "sub", "idx", "vtt", "nfo"],
+        ),
+        SidecarRule::new("Shapefiles", &["shp"], &["shx", "dbf", "prj", "cpg", "sbn", "sbx", "qix"]),
+    ]
+}
+
+/// Finds the primary file each sidecar among `files` belongs to, looking for
+/// primary files among `candidates`. These should include files that are left
+/// alone, so that a sidecar can stay with a primary file that is not moved.
+///
+/// Returns a map from each sidecar to its primary file. A sidecar matching
+/// several primary files belongs to the one with the longest stem.
+pub(crate) fn find_primaries(
+    files: &[PathBuf],
+    candidates: &[PathBuf],
+    rules: &[SidecarRule],
+    compound_extensions: &[String],
+) -> HashMap<PathBuf, PathBuf> {
+    let listed = |extensions: &[String], extension: &str| extensions.iter().any(|e| e.eq_ignore_ascii_case(extension));
+
+    let mut by_directory: HashMap<&Path, Vec<&PathBuf>> = HashMap::new();
+    for candidate in candidates {
+        by_directory.entry(candidate.parent().unwrap_or(Path::new(""))).or_default().push(candidate);
+    }
+
+    let mut primaries = HashMap::new();
+    for file in files {
+        let Some(extension) = extension_of(file, compound_extensions) else {
+            continue;
+        };
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        let (stem, _) = split_extension(&name, compound_extensions);
+        let Some(siblings) = by_directory.get(file.parent().unwrap_or(Path::new(""))) else {
+            continue;
+        };
+
+        let primary = rules
+            .iter()
+            .filter(|rule| listed(&rule.sidecar_extensions, &extension))
+            .flat_map(|rule| {
+                siblings.iter().filter(|&&candidate| {
+                    candidate != file
+                        && extension_of(candidate, compound_extensions)
+                            .is_some_and(|extension| listed(&rule.primary_extensions, &extension))
+                })
+            })
+            .filter_map(|&candidate| {
+                let candidate_name = candidate.file_name().unwrap_or_default().to_string_lossy();
+                let (candidate_stem, _) = split_extension(&candidate_name, compound_extensions);
+                let follows = stem == candidate_stem
+                    || stem.strip_prefix(candidate_stem).is_some_and(|rest| rest.starts_with('.'));
+                follows.then_some((candidate_stem.len(), candidate))
+            })
+            .max_by(|(a_len, a), (b_len, b)| a_len.cmp(b_len).then_with(|| b.cmp(a)));
+
+        if let Some((_, primary)) = primary {
+            primaries.insert(file.clone(), primary.clone());
+        }
+    }
+    primaries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::default_compound_extensions;
+
+    fn primaries(files: &[&str], candidates: &[&str]) -> Vec<(String, String)> {
+        let paths = |names: &[&str]| names.iter().map(PathBuf::from).collect::<Vec<_>>();
+        let found = find_primaries(
+            &paths(files),
+            &paths(candidates),
+            &default_sidecar_rules(),
+            &default_compound_extensions(),
+        );
+        let mut found: Vec<_> = found
+            .into_iter()
+            .map(|(sidecar, primary)| (sidecar.to_string_lossy().into_owned(), primary.to_string_lossy().into_owned()))
+            .collect();
+        found.sort();
+        found
+    }
+
+    fn pair(sidecar: &str, primary: &str) -> (String, String) {
+        (sidecar.to_owned(), primary.to_owned())
+    }
+
+    #[test]
+    fn pairs_sidecars_with_their_primary_file() {
+        let files =
+            ["in/IMG_0001.CR2", "in/IMG_0001.xmp", "in/movie.mkv", "in/movie.en.srt", "in/map.shp", "in/map.dbf"];
+        assert_eq!(
+            primaries(&files, &files),
+            [
+                pair("in/IMG_0001.xmp", "in/IMG_0001.CR2"),
+                pair("in/map.dbf", "in/map.shp"),
+                pair("in/movie.en.srt", "in/movie.mkv"),
+            ]
+        );
+    }
+
+    #[test]
+    fn needs_a_primary_file_in_the_same_directory() {
+        let files = ["in/IMG_0001.xmp", "in/other.CR2", "elsewhere/IMG_0001.CR2", "in/IMG_0001.txt"];
+        assert!(primaries(&files, &files).is_empty());
+    }
+
+    #[test]
+    fn prefers_the_longest_matching_stem() {
+        let files = ["in/movie.mkv", "in/movie.part2.mkv", "in/movie.part2.srt"];
+        assert_eq!(primaries(&files, &files), [pair("in/movie.part2.srt", "in/movie.part2.mkv")]);
+    }
+
+    #[test]
+    fn finds_primary_files_that_are_left_alone() {
+        let files = ["in/IMG_0001.xmp"];
+        let candidates = ["in/IMG_0001.xmp", "in/IMG_0001.dng"];
+        assert_eq!(primaries(&files, &candidates), [pair("in/IMG_0001.xmp", "in/IMG_0001.dng")]);
+    }
+}
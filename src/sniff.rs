@@ -0,0 +1,174 @@
+//! File type detection from content ("magic bytes").
+//!
+//! Extensions are missing or wrong surprisingly often. [`sniff_extension`] reads
+//! the first bytes of a file and recognizes common formats by their signature,
+//! so that `Config::content_detection` can classify such files correctly.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// When to look at file contents to decide a file's type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentDetection {
+    /// Classify by extension only.
+    #[default]
+    Off,
+    /// Look at the content of files that have no extension.
+    Fallback,
+    /// Also look at files with an extension, and classify by content when the
+    /// extension does not match it.
+    Override,
+}
+
+impl ContentDetection {
+    /// Every mode, in the order they are offered to users.
+    pub const ALL: [ContentDetection; 3] = [ContentDetection::Off, ContentDetection::Fallback, ContentDetection::Override];
+
+    /// The name used for the mode in configuration files and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContentDetection::Off => "off",
+            ContentDetection::Fallback => "fallback",
+            ContentDetection::Override => "override",
+        }
+    }
+
+    /// A short human-readable description of the mode.
+    pub fn label(&self) -> &'static str {
+        match self {
+            ContentDetection::Off => "Off",
+            ContentDetection::Fallback => "For files without extension",
+            ContentDetection::Override => "Also fix wrong extensions",
+        }
+    }
+}
+
+impl FromStr for ContentDetection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ContentDetection::ALL
+            .into_iter()
+            .find(|mode| mode.name() == s)
+            .ok_or_else(|| format!("unknown content detection mode '{}', expected one of: off, fallback, override", s))
+    }
+}
+
+/// A recognizable file format.
+struct Signature {
+    /// Byte patterns that must all appear at the given offsets.
+    parts: &'static [(usize, &'static [u8])],
+    /// The extension the format is classified as.
+    extension: &'static str,
+    /// Other extensions commonly used for files with this signature, which are
+    /// therefore not considered wrong.
+    aliases: &'static [&'static str],
+}
+
+/// Known signatures. More specific signatures come before more general ones.
+const SIGNATURES: &[Signature] = &[
+    Signature { parts: &[(0, b"\x89PNG\r\n\x1a\n")], extension: "png", aliases: &["apng"] },
+    Signature { parts: &[(0, b"\xff\xd8\xff")], extension: "jpg", aliases: &["jpeg", "jpe", "jfif"] },
+    Signature { parts: &[(0, b"GIF87a")], extension: "gif", aliases: &[] },
+    Signature { parts: &[(0, b"GIF89a")], extension: "gif", aliases: &[] },
+    Signature { parts: &[(0, b"RIFF"), (8, b"WEBP")], extension: "webp", aliases: &[] },
+    Signature { parts: &[(0, b"RIFF"), (8, b"WAVE")], extension: "wav", aliases: &[] },
+    Signature { parts: &[(0, b"RIFF"), (8, b"AVI ")], extension: "avi", aliases: &[] },
+    Signature {
+        parts: &[(0, b"II*\0")],
+        extension: "tif",
+        aliases: &["tiff", "cr2", "nef", "arw", "dng", "orf", "rw2"],
+    },
+    Signature {
+        parts: &[(0, b"MM\0*")],
+        extension: "tif",
+        aliases: &["tiff", "cr2", "nef", "arw", "dng", "orf", "rw2"],
+    },
+    Signature { parts: &[(0, b"%PDF-")], extension: "pdf", aliases: &["ai"] },
+    Signature {
+        parts: &[(0, b"PK\x03\x04")],
+        extension: "zip",
+        aliases: &[
+            "docx", "xlsx", "pptx", "odt", "ods", "odp", "epub", "jar", "apk", "xpi", "whl", "cbz", "kmz", "3mf",
+        ],
+    },
+    Signature { parts: &[(0, b"PK\x05\x06")], extension: "zip", aliases: &["docx", "xlsx", "pptx", "jar"] },
+    Signature { parts: &[(0, b"Rar!\x1a\x07")], extension: "rar", aliases: &["cbr"] },
+    Signature { parts: &[(0, b"7z\xbc\xaf\x27\x1c")], extension: "7z", aliases: &[] },
+    Signature { parts: &[(0, b"\x1f\x8b")], extension: "gz", aliases: &["tgz", "tar.gz", "svgz"] },
+    Signature { parts: &[(0, b"BZh")], extension: "bz2", aliases: &["tbz2", "tar.bz2"] },
+    Signature { parts: &[(0, b"\xfd7zXZ\0")], extension: "xz", aliases: &["txz", "tar.xz"] },
+    Signature { parts: &[(0, b"\x28\xb5\x2f\xfd")], extension: "zst", aliases: &["tzst", "tar.zst"] },
+    Signature { parts: &[(257, b"ustar")], extension: "tar", aliases: &[] },
+    Signature { parts: &[(0, b"\x7fELF")], extension: "elf", aliases: &["so", "o", "bin", "out", "axf"] },
+    Signature { parts: &[(0, b"MZ")], extension: "exe", aliases: &["dll", "sys", "scr", "com", "efi"] },
+    Signature { parts: &[(0, b"\0asm")], extension: "wasm", aliases: &[] },
+    Signature { parts: &[(0, b"SQLite format 3\0")], extension: "sqlite", aliases: &["db", "sqlite3", "db3"] },
+    Signature { parts: &[(0, b"ID3")], extension: "mp3", aliases: &[] },
+    Signature { parts: &[(0, b"\xff\xfb")], extension: "mp3", aliases: &[] },
+    Signature { parts: &[(0, b"\xff\xf3")], extension: "mp3", aliases: &[] },
+    Signature { parts: &[(0, b"\xff\xf2")], extension: "mp3", aliases: &[] },
+    Signature { parts: &[(0, b"fLaC")], extension: "flac", aliases: &[] },
+    Signature { parts: &[(0, b"OggS")], extension: "ogg", aliases: &["oga", "ogv", "opus", "spx"] },
+    Signature { parts: &[(0, b"\x1a\x45\xdf\xa3")], extension: "mkv", aliases: &["webm", "mka", "mk3d"] },
+    Signature { parts: &[(4, b"ftypM4A ")], extension: "m4a", aliases: &["m4b", "mp4"] },
+    Signature { parts: &[(4, b"ftypqt  ")], extension: "mov", aliases: &["qt"] },
+    Signature { parts: &[(4, b"ftypheic")], extension: "heic", aliases: &["heif"] },
+    Signature { parts: &[(4, b"ftypmif1")], extension: "heic", aliases: &["heif", "avif"] },
+    Signature { parts: &[(4, b"ftypavif")], extension: "avif", aliases: &[] },
+    Signature { parts: &[(4, b"ftyp")], extension: "mp4", aliases: &["m4v", "m4a", "mov", "3gp", "3g2", "f4v"] },
+];
+
+/// Number of leading bytes needed to check every signature.
+const HEADER_LEN: usize = 262;
+
+/// Reads the start of the file at `path` and returns the extension of the format
+/// its content matches, if it is recognized.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the file cannot be read.
+pub fn sniff_extension(path: &Path) -> io::Result<Option<&'static str>> {
+    Ok(sniff(path)?.map(|signature| signature.extension))
+}
+
+/// Decides which extension to classify a file by, given its actual extension
+/// (lowercased, if any) and the detection mode.
+///
+/// Files that cannot be read are classified by their extension alone.
+pub(crate) fn detect(path: &Path, extension: Option<String>, mode: ContentDetection) -> Option<String> {
+    let should_sniff = match mode {
+        ContentDetection::Off => false,
+        ContentDetection::Fallback => extension.is_none(),
+        ContentDetection::Override => true,
+    };
+    if !should_sniff {
+        return extension;
+    }
+
+    match sniff(path).ok().flatten() {
+        Some(signature) => match extension {
+            Some(extension) if extension == signature.extension || signature.aliases.contains(&extension.as_str()) => {
+                Some(extension)
+            }
+            _ => Some(signature.extension.to_owned()),
+        },
+        None => extension,
+    }
+}
+
+fn sniff(path: &Path) -> io::Result<Option<&'static Signature>> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    File::open(path)?.take(HEADER_LEN as u64).read_to_end(&mut header)?;
+
+    Ok(SIGNATURES.iter().find(|signature| {
+        signature
+            .parts
+            .iter()
+            .all(|(offset, magic)| header.get(*offset..offset + magic.len()) == Some(*magic))
+    }))
+}
@@ -0,0 +1,76 @@
+//! Symbolic links found in the organized directory.
+//!
+//! Renaming a link moves the link, while copying one copies what it points to,
+//! so links are never left to whichever operation happens to touch them.
+//! [`SymlinkPolicy`] decides whether they are left alone, moved as links, or
+//! followed so that what they point to is organized. Broken links are always
+//! left alone and reported with their target.
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// What to do with symbolic links in the organized directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    /// Links are left where they are.
+    #[default]
+    Skip,
+    /// The link itself is organized by its own name, and keeps pointing at the
+    /// same path from its new place. A link to a file that is organized in the
+    /// same run therefore points at where the file used to be.
+    MoveLink,
+    /// The file a link points to is organized in its place, and links to
+    /// directories are descended into when organizing recursively. The link
+    /// itself is left where it is. What lies outside the organized directory is
+    /// copied rather than moved, and links into protected locations or to a
+    /// directory containing the organized directory are skipped.
+    Follow,
+}
+
+impl SymlinkPolicy {
+    /// Every policy, in the order they are offered to users.
+    pub const ALL: [SymlinkPolicy; 3] = [SymlinkPolicy::Skip, SymlinkPolicy::MoveLink, SymlinkPolicy::Follow];
+
+    /// The name used for the policy in configuration files and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SymlinkPolicy::Skip => "skip",
+            SymlinkPolicy::MoveLink => "move_link",
+            SymlinkPolicy::Follow => "follow",
+        }
+    }
+
+    /// A short human-readable description of the policy.
+    pub fn label(&self) -> &'static str {
+        match self {
+            SymlinkPolicy::Skip => "Leave them alone",
+            SymlinkPolicy::MoveLink => "Move the links themselves",
+            SymlinkPolicy::Follow => "Organize what they point to",
+        }
+    }
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SymlinkPolicy::ALL
+            .into_iter()
+            .find(|policy| policy.name() == s)
+            .ok_or_else(|| format!("unknown symlink policy '{}', expected one of: skip, move_link, follow", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_policy_names() {
+        for policy in SymlinkPolicy::ALL {
+            assert_eq!(policy.name().parse::<SymlinkPolicy>(), Ok(policy));
+        }
+        assert!("move".parse::<SymlinkPolicy>().is_err());
+    }
+}
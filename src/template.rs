@@ -0,0 +1,405 @@
+//! Placeholder templates for destination paths.
+//!
+//! A template such as `{category}/{year}/{month:02}` is a relative path in which
+//! `{placeholder}` is replaced by a value describing the file being organized.
+//! Numeric placeholders may carry a zero-padding width, e.g. `{month:02}`
+//! renders March as `03`. The available placeholders are:
+//!
+//! * `{category}` - the file's category folder, as it would be without a template
+//! * `{ext}` - the lowercased extension, or the "others" folder name if there is none
+//! * `{year}`, `{month}`, `{day}` - the file's date
+//! * `{size_bucket}` - `small` (under 1 MB), `medium` (under 100 MB),
+//!   `large` (under 1 GB) or `huge`
+//! * `{first_letter}` - the uppercased first letter or digit of the file name,
+//!   or `#` if it starts with anything else
+//! * `{parent}` - the name of the directory the file is in
+//!
+//! A value that is empty, `.` or `..` renders as `_`, and path separators in
+//! a value are replaced by `_`, so every placeholder renders as exactly one
+//! path component.
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A value that can be substituted into a template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    Category,
+    Extension,
+    Year,
+    Month,
+    Day,
+    SizeBucket,
+    FirstLetter,
+    Parent,
+}
+
+impl Placeholder {
+    const ALL: [(&'static str, Placeholder); 8] = [
+        ("category", Placeholder::Category),
+        ("ext", Placeholder::Extension),
+        ("year", Placeholder::Year),
+        ("month", Placeholder::Month),
+        ("day", Placeholder::Day),
+        ("size_bucket", Placeholder::SizeBucket),
+        ("first_letter", Placeholder::FirstLetter),
+        ("parent", Placeholder::Parent),
+    ];
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Placeholder::Year | Placeholder::Month | Placeholder::Day)
+    }
+}
+
+/// The largest zero-padding width a placeholder may ask for.
+const MAX_WIDTH: usize = 10;
+
+/// The names `{size_bucket}` renders to, with the exclusive upper size limit of each.
+const SIZE_BUCKETS: [(&str, u64); 4] = [
+    ("small", 1 << 20),
+    ("medium", 100 << 20),
+    ("large", 1 << 30),
+    ("huge", u64::MAX),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder { placeholder: Placeholder, width: usize },
+}
+
+/// A parsed destination template.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+/// An error in the syntax of a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateError(String);
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// The values describing a file, used to render a template.
+pub(crate) struct TemplateValues<'a> {
+    pub(crate) category: &'a str,
+    pub(crate) extension: &'a str,
+    pub(crate) date: NaiveDate,
+    pub(crate) size: u64,
+    pub(crate) file_name: &'a str,
+    pub(crate) parent: &'a str,
+}
+
+impl FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        if source.trim().is_empty() {
+            return Err(TemplateError("template must not be empty".to_owned()));
+        }
+        if source.starts_with(['/', '\\']) {
+            return Err(TemplateError("template must be a relative path".to_owned()));
+        }
+
+        let mut segments = Vec::new();
+        let mut rest = source;
+        while !rest.is_empty() {
+            match rest.find(['{', '}']) {
+                Some(0) if rest.starts_with('}') => {
+                    return Err(TemplateError("unmatched `}`".to_owned()));
+                }
+                Some(0) => {
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| TemplateError("unclosed `{`".to_owned()))?;
+                    segments.push(parse_placeholder(&rest[1..end])?);
+                    rest = &rest[end + 1..];
+                }
+                Some(start) => {
+                    segments.push(Segment::Literal(rest[..start].to_owned()));
+                    rest = &rest[start..];
+                }
+                None => {
+                    segments.push(Segment::Literal(rest.to_owned()));
+                    rest = "";
+                }
+            }
+        }
+
+        for segment in &segments {
+            if let Segment::Literal(text) = segment {
+                if text.split(['/', '\\']).any(|component| component == "..") {
+                    return Err(TemplateError("template must not contain `..`".to_owned()));
+                }
+            }
+        }
+
+        Ok(Template { source: source.to_owned(), segments })
+    }
+}
+
+fn parse_placeholder(text: &str) -> Result<Segment, TemplateError> {
+    let (name, width) = match text.split_once(':') {
+        Some((name, width)) => {
+            let width = width
+                .parse()
+                .map_err(|_| TemplateError(format!("invalid width `{}` for `{{{}}}`", width, name)))?;
+            (name, width)
+        }
+        None => (text, 0),
+    };
+
+    let placeholder = Placeholder::ALL
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, placeholder)| *placeholder)
+        .ok_or_else(|| {
+            let known: Vec<_> = Placeholder::ALL.iter().map(|(known, _)| format!("{{{}}}", known)).collect();
+            TemplateError(format!("unknown placeholder `{{{}}}`, expected one of: {}", name, known.join(", ")))
+        })?;
+
+    if width > 0 && !placeholder.is_numeric() {
+        return Err(TemplateError(format!("`{{{}}}` does not take a width", name)));
+    }
+    if width > MAX_WIDTH {
+        return Err(TemplateError(format!("width of `{{{}}}` must be at most {}", name, MAX_WIDTH)));
+    }
+
+    Ok(Segment::Placeholder { placeholder, width })
+}
+
+impl TryFrom<String> for Template {
+    type Error = TemplateError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        source.parse()
+    }
+}
+
+impl From<Template> for String {
+    fn from(template: Template) -> Self {
+        template.source
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Template {
+    /// Renders the template for a file described by `values`, giving a relative path.
+    pub(crate) fn render(&self, values: &TemplateValues) -> String {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => rendered.push_str(text),
+                Segment::Placeholder { placeholder, width } => {
+                    rendered.push_str(&path_component(render_placeholder(*placeholder, *width, values)));
+                }
+            }
+        }
+        rendered
+    }
+
+    /// Returns `true` if `name` could be the first path component of a
+    /// rendering of this template. `is_category` tells whether a name is a
+    /// possible `{category}` value. `{ext}` and `{parent}` can be almost
+    /// anything, so a component containing them never matches.
+    pub(crate) fn matches_first_component(&self, name: &str, is_category: &dyn Fn(&str) -> bool) -> bool {
+        let mut first_component = Vec::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => match text.find(['/', '\\']) {
+                    Some(end) => {
+                        first_component.push(Segment::Literal(text[..end].to_owned()));
+                        break;
+                    }
+                    None => first_component.push(segment.clone()),
+                },
+                Segment::Placeholder { .. } => first_component.push(segment.clone()),
+            }
+        }
+        matches_segments(&first_component, name, is_category)
+    }
+}
+
+fn render_placeholder(placeholder: Placeholder, width: usize, values: &TemplateValues) -> String {
+    match placeholder {
+        Placeholder::Category => values.category.to_owned(),
+        Placeholder::Extension => values.extension.to_owned(),
+        Placeholder::Year => format!("{:0width$}", values.date.year(), width = width),
+        Placeholder::Month => format!("{:0width$}", values.date.month(), width = width),
+        Placeholder::Day => format!("{:0width$}", values.date.day(), width = width),
+        Placeholder::SizeBucket => size_bucket(values.size).to_owned(),
+        Placeholder::FirstLetter => first_letter(values.file_name).to_string(),
+        Placeholder::Parent => values.parent.to_owned(),
+    }
+}
+
+/// Makes `value` safe to use as (part of) a single path component.
+fn path_component(value: String) -> String {
+    match value.as_str() {
+        "" | "." | ".." => "_".to_owned(),
+        _ => value.replace(['/', '\\'], "_"),
+    }
+}
+
+/// Matches `name` against `segments`, trying every split for variable-length values.
+fn matches_segments(segments: &[Segment], name: &str, is_category: &dyn Fn(&str) -> bool) -> bool {
+    let Some((segment, rest)) = segments.split_first() else {
+        return name.is_empty();
+    };
+
+    match segment {
+        Segment::Literal(text) => name
+            .strip_prefix(text.as_str())
+            .is_some_and(|remaining| matches_segments(rest, remaining, is_category)),
+        Segment::Placeholder { placeholder, .. } => (1..=name.len())
+            .filter(|&end| name.is_char_boundary(end))
+            .any(|end| {
+                let value = &name[..end];
+                let plausible = match placeholder {
+                    Placeholder::Year | Placeholder::Month | Placeholder::Day => {
+                        value.chars().all(|c| c.is_ascii_digit())
+                    }
+                    Placeholder::Category => is_category(value),
+                    Placeholder::SizeBucket => SIZE_BUCKETS.iter().any(|(bucket, _)| *bucket == value),
+                    Placeholder::FirstLetter => value.chars().count() == 1,
+                    Placeholder::Extension | Placeholder::Parent => false,
+                };
+                plausible && matches_segments(rest, &name[end..], is_category)
+            }),
+    }
+}
+
+fn size_bucket(size: u64) -> &'static str {
+    SIZE_BUCKETS
+        .iter()
+        .find(|(_, limit)| size < *limit)
+        .map_or("huge", |(bucket, _)| bucket)
+}
+
+fn first_letter(file_name: &str) -> char {
+    match file_name.chars().next() {
+        Some(c) if c.is_alphanumeric() => c.to_uppercase().next().unwrap_or(c),
+        _ => '#',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values<'a>(category: &'a str, extension: &'a str, file_name: &'a str) -> TemplateValues<'a> {
+        TemplateValues {
+            category,
+            extension,
+            date: NaiveDate::from_ymd_opt(2024, 3, 7).unwrap(),
+            size: 5 << 20,
+            file_name,
+            parent: "Downloads",
+        }
+    }
+
+    fn render(template: &str, values: &TemplateValues) -> String {
+        template.parse::<Template>().unwrap().render(values)
+    }
+
+    #[test]
+    fn parses_placeholders_and_literals() {
+        let template: Template = "{category}/{year}-{month:02}".parse().unwrap();
+        assert_eq!(
+            template.segments,
+            vec![
+                Segment::Placeholder { placeholder: Placeholder::Category, width: 0 },
+                Segment::Literal("/".to_owned()),
+                Segment::Placeholder { placeholder: Placeholder::Year, width: 0 },
+                Segment::Literal("-".to_owned()),
+                Segment::Placeholder { placeholder: Placeholder::Month, width: 2 },
+            ]
+        );
+        assert_eq!(template.to_string(), "{category}/{year}-{month:02}");
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        for source in [
+            "",
+            "  ",
+            "/absolute/{year}",
+            "\\absolute",
+            "{year",
+            "year}",
+            "{unknown}",
+            "{category:2}",
+            "{month:x}",
+            "{year:11}",
+            "{year:70000}",
+            "../{year}",
+            "{category}/../up",
+        ] {
+            assert!(source.parse::<Template>().is_err(), "{:?} should be rejected", source);
+        }
+        assert!("{year:10}".parse::<Template>().is_ok());
+    }
+
+    #[test]
+    fn renders_every_placeholder() {
+        let values = values("Images", "jpg", "holiday.jpg");
+        assert_eq!(render("{category}/{ext}/{parent}", &values), "Images/jpg/Downloads");
+        assert_eq!(render("{year}/{month:02}/{day:02}", &values), "2024/03/07");
+        assert_eq!(render("{year}/{month}/{day}", &values), "2024/3/7");
+        assert_eq!(render("{size_bucket}/{first_letter}", &values), "medium/H");
+        assert_eq!(render("{first_letter}", &TemplateValues { file_name: "_x", ..values }), "#");
+    }
+
+    #[test]
+    fn renders_each_placeholder_as_one_component() {
+        let empty = values("", "", "x");
+        assert_eq!(render("{category}/{ext}", &empty), "_/_");
+        assert_eq!(render("{ext}", &values(".", "..", "x")), "_");
+        assert_eq!(render("{category}", &values("a/b\\c", "", "x")), "a_b_c");
+    }
+
+    #[test]
+    fn size_buckets_have_exclusive_limits() {
+        assert_eq!(size_bucket(0), "small");
+        assert_eq!(size_bucket((1 << 20) - 1), "small");
+        assert_eq!(size_bucket(1 << 20), "medium");
+        assert_eq!(size_bucket(1 << 30), "huge");
+        assert_eq!(size_bucket(u64::MAX), "huge");
+    }
+
+    #[test]
+    fn matches_the_first_component_of_a_rendering() {
+        let is_category = |name: &str| name == "Images" || name == "Documents";
+        let template: Template = "{category}/{year}".parse().unwrap();
+        assert!(template.matches_first_component("Images", &is_category));
+        assert!(!template.matches_first_component("Holiday", &is_category));
+        assert!(!template.matches_first_component("", &is_category));
+
+        let template: Template = "{year}-{month:02}/{category}".parse().unwrap();
+        assert!(template.matches_first_component("2024-03", &is_category));
+        assert!(!template.matches_first_component("2024", &is_category));
+        assert!(!template.matches_first_component("March-03", &is_category));
+
+        let template: Template = "Sorted {size_bucket}".parse().unwrap();
+        assert!(template.matches_first_component("Sorted large", &is_category));
+        assert!(!template.matches_first_component("Sorted big", &is_category));
+
+        let template: Template = "{ext}/{year}".parse().unwrap();
+        assert!(!template.matches_first_component("jpg", &is_category));
+    }
+}
@@ -0,0 +1,487 @@
+//! Moving and copying files safely, including across file systems.
+//!
+//! A file can only be renamed within one file system. When the destination lives
+//! on another mount, [`move_file`] falls back to copying the file, flushing the
+//! copy to disk, optionally verifying it by checksum, and only then removing the
+//! source, so an interrupted or failed transfer never loses data.
+//!
+//! Whole directories, such as projects, are moved and copied the same way.
+//! Symbolic links are recreated at their destination, pointing at the same
+//! target, rather than renamed or copied through.
+//!
+//! Duplicate files can also be replaced with hard links to an identical file,
+//! and turned back into independent copies when a run is undone.
+
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::digest::{file_digest, same_content};
+
+/// Size of the buffer used to stream file contents.
+const BUFFER_SIZE: usize = 256 * 1024;
+
+/// How a file reached its destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Transfer {
+    /// The file was renamed within its file system.
+    Renamed,
+    /// The file was copied, and for a move its source then removed.
+    Copied,
+    /// The symbolic link was recreated at the destination, and for a move the
+    /// original link removed.
+    Relinked,
+}
+
+/// Moves the file, directory or symbolic link at `source` to `destination`,
+/// which must not exist yet.
+///
+/// If both are on the same file system it is simply renamed. Otherwise it is
+/// copied with [`copy_file`] or [`copy_directory`] and the source removed once
+/// the copy is safely on disk. A symbolic link is recreated with [`relink`].
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the file cannot be renamed or copied, or if
+/// `verify` is set and the copy does not match the source. The source is left
+/// in place and any partial copy removed.
+pub(crate) fn move_file(source: &Path, destination: &Path, verify: bool) -> io::Result<Transfer> {
+    if source.is_symlink() {
+        relink(source, destination)?;
+        fs::remove_file(source)?;
+        return Ok(Transfer::Relinked);
+    }
+    match fs::rename(source, destination) {
+        Ok(()) => Ok(Transfer::Renamed),
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            if source.is_dir() {
+                copy_directory(source, destination, verify)?;
+                fs::remove_dir_all(source)?;
+            } else {
+                copy_file(source, destination, verify)?;
+                fs::remove_file(source)?;
+            }
+            Ok(Transfer::Copied)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Like [`move_file`], but replaces the file already at `destination`.
+///
+/// Within one file system the rename replaces it atomically. Otherwise the new
+/// content is copied next to it with [`copy_replacing`], and the source only
+/// removed once the copy has taken the existing file's place.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the file cannot be moved, in which case both
+/// the source and the existing file are left as they were.
+pub(crate) fn move_replacing(source: &Path, destination: &Path, verify: bool) -> io::Result<Transfer> {
+    let is_link = source.is_symlink();
+    if !is_link {
+        match fs::rename(source, destination) {
+            Ok(()) => return Ok(Transfer::Renamed),
+            Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {}
+            Err(err) => return Err(err),
+        }
+    }
+    copy_replacing(source, destination, verify)?;
+    fs::remove_file(source)?;
+    Ok(if is_link { Transfer::Relinked } else { Transfer::Copied })
+}
+
+/// Copies the file or symbolic link at `source` over the file already at
+/// `destination`. The copy is made under a temporary name next to it and then
+/// renamed into place, so the existing file is only replaced by a complete copy.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the copy fails, leaving `destination` unchanged.
+pub(crate) fn copy_replacing(source: &Path, destination: &Path, verify: bool) -> io::Result<()> {
+    let temporary = temporary_path(destination);
+    if source.is_symlink() {
+        relink(source, &temporary)?;
+    } else {
+        copy_file(source, &temporary, verify)?;
+    }
+    replace(&temporary, destination)
+}
+
+/// Copies the file at `source` to `destination`, which must not exist yet.
+///
+/// The content is streamed in chunks and flushed to disk before returning. The
+/// permissions and access and modification times of the source are preserved.
+/// With `verify`, the copy is read back and its SHA-256 digest compared with that
+/// of the source.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the copy fails or does not match the source, in
+/// which case the partial copy is removed. A file already at `destination` is
+/// an error and left alone.
+pub(crate) fn copy_file(source: &Path, destination: &Path, verify: bool) -> io::Result<()> {
+    copy_contents(source, destination, verify)
+}
+
+/// Copies the directory at `source`, with everything in it, to `destination`,
+/// which must not exist yet. Files are copied with [`copy_file`], and symbolic
+/// links are recreated rather than followed.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if anything cannot be copied, in which case the
+/// partial copy is removed.
+pub(crate) fn copy_directory(source: &Path, destination: &Path, verify: bool) -> io::Result<()> {
+    fs::create_dir(destination)?;
+    let result = copy_directory_contents(source, destination, verify);
+    if result.is_err() {
+        let _ = fs::remove_dir_all(destination);
+    }
+    result
+}
+
+fn copy_directory_contents(source: &Path, destination: &Path, verify: bool) -> io::Result<()> {
+    for item in fs::read_dir(source)? {
+        let item = item?;
+        let file_type = item.file_type()?;
+        let target = destination.join(item.file_name());
+        if file_type.is_dir() {
+            fs::create_dir(&target)?;
+            copy_directory_contents(&item.path(), &target, verify)?;
+        } else if file_type.is_symlink() {
+            copy_link(&item.path(), &target)?;
+        } else {
+            copy_contents(&item.path(), &target, verify)?;
+        }
+    }
+    fs::set_permissions(destination, fs::metadata(source)?.permissions())
+}
+
+/// Recreates the symbolic link at `source` as `destination`.
+fn copy_link(source: &Path, destination: &Path) -> io::Result<()> {
+    symlink_like(source, fs::read_link(source)?, destination)
+}
+
+/// Recreates the symbolic link at `source` as `destination`, somewhere else.
+/// A relative target is made absolute, so that the new link still points at
+/// the same place.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the link cannot be read or created.
+pub(crate) fn relink(source: &Path, destination: &Path) -> io::Result<()> {
+    let target = fs::read_link(source)?;
+    let target = match source.parent() {
+        Some(parent) if target.is_relative() => std::path::absolute(parent)?.join(target),
+        _ => target,
+    };
+    symlink_like(source, target, destination)
+}
+
+/// Moves the symbolic link at `path` back to `original`, pointing at `target`
+/// exactly as it did before it was moved.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the link cannot be created or the moved link
+/// cannot be removed.
+pub(crate) fn restore_link(path: &Path, original: &Path, target: &Path) -> io::Result<()> {
+    symlink_like(path, target.to_path_buf(), original)?;
+    fs::remove_file(path)
+}
+
+/// Creates a symbolic link to `target` at `destination`, of the same kind as
+/// the link at `source` where the platform distinguishes links to files and
+/// to directories.
+#[cfg(unix)]
+fn symlink_like(_source: &Path, target: PathBuf, destination: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, destination)
+}
+
+#[cfg(windows)]
+fn symlink_like(source: &Path, target: PathBuf, destination: &Path) -> io::Result<()> {
+    if source.is_dir() {
+        std::os::windows::fs::symlink_dir(target, destination)
+    } else {
+        std::os::windows::fs::symlink_file(target, destination)
+    }
+}
+
+/// Total size in bytes of the files in the directory at `path`, however deeply nested.
+pub(crate) fn directory_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for item in fs::read_dir(path)? {
+        let item = item?;
+        let file_type = item.file_type()?;
+        if file_type.is_dir() {
+            size += directory_size(&item.path())?;
+        } else if file_type.is_file() {
+            size += item.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+fn copy_contents(source: &Path, destination: &Path, verify: bool) -> io::Result<()> {
+    let reader = File::open(source)?;
+    let writer = OpenOptions::new().write(true).create_new(true).open(destination)?;
+    // Only the file created here may be removed again.
+    let result = write_copy(reader, writer, source, destination, verify);
+    if result.is_err() {
+        let _ = fs::remove_file(destination);
+    }
+    result
+}
+
+/// Streams `reader` into the newly created `writer`, then gives the copy the
+/// permissions and times of the source and flushes it to disk.
+fn write_copy(mut reader: File, mut writer: File, source: &Path, destination: &Path, verify: bool) -> io::Result<()> {
+    let metadata = reader.metadata()?;
+
+    let mut hasher = verify.then(Sha256::new);
+    let mut buffer = vec![0; BUFFER_SIZE];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        writer.write_all(&buffer[..read])?;
+        if let Some(hasher) = &mut hasher {
+            hasher.update(&buffer[..read]);
+        }
+    }
+
+    writer.set_permissions(metadata.permissions())?;
+    let mut times = FileTimes::new();
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    if let Ok(modified) = metadata.modified() {
+        times = times.set_modified(modified);
+    }
+    writer.set_times(times)?;
+    writer.sync_all()?;
+    drop(writer);
+    sync_parent(destination)?;
+
+    if let Some(hasher) = hasher {
+        let expected: [u8; 32] = hasher.finalize().into();
+        if file_digest(destination)? != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("copy of {} to {} does not match the original", source.display(), destination.display()),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the file at `path` with a hard link to `target`.
+///
+/// The link is created under a temporary name and renamed over `path`, so a
+/// file exists at `path` throughout.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if either file cannot be read, their contents
+/// differ (for instance because one changed since the run was planned), or the
+/// link cannot be created, e.g. because they are on different file systems.
+pub(crate) fn replace_with_hard_link(path: &Path, target: &Path) -> io::Result<()> {
+    if !same_content(path, target)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is no longer identical to {}", path.display(), target.display()),
+        ));
+    }
+    let temporary = temporary_path(path);
+    fs::hard_link(target, &temporary)?;
+    replace(&temporary, path)
+}
+
+/// Replaces the file at `path`, typically a hard link, with an independent copy
+/// of its content.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the copy fails, leaving `path` unchanged.
+pub(crate) fn replace_with_copy(path: &Path, verify: bool) -> io::Result<()> {
+    let temporary = temporary_path(path);
+    copy_file(path, &temporary, verify)?;
+    replace(&temporary, path)
+}
+
+/// Renames `temporary` over `path`, removing `temporary` if that fails.
+fn replace(temporary: &Path, path: &Path) -> io::Result<()> {
+    let result = fs::rename(temporary, path);
+    if result.is_err() {
+        let _ = fs::remove_file(temporary);
+    }
+    result
+}
+
+/// A hidden name next to `path` for a file that will replace it.
+fn temporary_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.fileorg-tmp", name))
+}
+
+/// Flushes the directory entry of a newly created file to disk, where the
+/// platform supports it.
+fn sync_parent(path: &Path) -> io::Result<()> {
+    if cfg!(unix) {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            File::open(parent)?.sync_all()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn copies_content_permissions_and_times() {
+        let scratch = tempfile::tempdir().unwrap();
+        let (source, destination) = (scratch.path().join("source"), scratch.path().join("copy"));
+        fs::write(&source, "content").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+        File::options().write(true).open(&source).unwrap().set_modified(modified).unwrap();
+        let mut permissions = fs::metadata(&source).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&source, permissions.clone()).unwrap();
+
+        copy_file(&source, &destination, true).unwrap();
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "content");
+        let metadata = fs::metadata(&destination).unwrap();
+        assert_eq!(metadata.permissions(), permissions);
+        assert_eq!(metadata.modified().unwrap(), modified);
+    }
+
+    #[test]
+    fn never_copies_over_an_existing_file() {
+        let scratch = tempfile::tempdir().unwrap();
+        let (source, destination) = (scratch.path().join("source"), scratch.path().join("existing"));
+        fs::write(&source, "new").unwrap();
+        fs::write(&destination, "existing").unwrap();
+
+        let err = copy_file(&source, &destination, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "existing");
+
+        fs::remove_file(&source).unwrap();
+        assert!(copy_file(&source, &destination, false).is_err());
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "existing");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn removes_a_partial_copy() {
+        let scratch = tempfile::tempdir().unwrap();
+        let destination = scratch.path().join("copy");
+        // A directory can be opened, but not read like a file.
+        assert!(copy_file(scratch.path(), &destination, false).is_err());
+        assert!(!destination.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn leaves_the_existing_file_when_replacing_fails() {
+        let scratch = tempfile::tempdir().unwrap();
+        let destination = scratch.path().join("existing");
+        fs::write(&destination, "existing").unwrap();
+
+        assert!(copy_replacing(scratch.path(), &destination, false).is_err());
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "existing");
+        assert!(!temporary_path(&destination).exists());
+    }
+
+    #[test]
+    fn replaces_an_existing_file() {
+        let scratch = tempfile::tempdir().unwrap();
+        let (source, destination) = (scratch.path().join("source"), scratch.path().join("existing"));
+        fs::write(&source, "new").unwrap();
+        fs::write(&destination, "existing").unwrap();
+
+        copy_replacing(&source, &destination, true).unwrap();
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "new");
+        assert!(source.exists());
+
+        fs::write(&destination, "existing").unwrap();
+        assert_eq!(move_replacing(&source, &destination, false).unwrap(), Transfer::Renamed);
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "new");
+        assert!(!source.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copies_directories_with_their_links() {
+        let scratch = tempfile::tempdir().unwrap();
+        let (source, destination) = (scratch.path().join("project"), scratch.path().join("copy"));
+        fs::create_dir_all(source.join("src")).unwrap();
+        fs::write(source.join("src/main.rs"), "fn main() {}").unwrap();
+        std::os::unix::fs::symlink("src/main.rs", source.join("link")).unwrap();
+
+        copy_directory(&source, &destination, true).unwrap();
+        assert_eq!(fs::read_to_string(destination.join("src/main.rs")).unwrap(), "fn main() {}");
+        assert_eq!(fs::read_link(destination.join("link")).unwrap(), Path::new("src/main.rs"));
+        assert_eq!(directory_size(&destination).unwrap(), 12);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn removes_a_partial_directory_copy() {
+        let scratch = tempfile::tempdir().unwrap();
+        let (source, destination) = (scratch.path().join("project"), scratch.path().join("copy"));
+        fs::create_dir(&source).unwrap();
+        fs::write(source.join("a.txt"), "a").unwrap();
+        // A socket looks like a file to copy but cannot be opened.
+        let _listener = std::os::unix::net::UnixListener::bind(source.join("socket")).unwrap();
+
+        assert!(copy_directory(&source, &destination, false).is_err());
+        assert!(!destination.exists());
+        assert!(source.join("a.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_only_identical_files() {
+        use std::os::unix::fs::MetadataExt;
+
+        let scratch = tempfile::tempdir().unwrap();
+        let (original, duplicate) = (scratch.path().join("original"), scratch.path().join("duplicate"));
+        fs::write(&original, "same").unwrap();
+        fs::write(&duplicate, "same").unwrap();
+
+        replace_with_hard_link(&duplicate, &original).unwrap();
+        assert_eq!(fs::metadata(&duplicate).unwrap().ino(), fs::metadata(&original).unwrap().ino());
+
+        replace_with_copy(&duplicate, false).unwrap();
+        assert_ne!(fs::metadata(&duplicate).unwrap().ino(), fs::metadata(&original).unwrap().ino());
+        assert_eq!(fs::read_to_string(&duplicate).unwrap(), "same");
+
+        fs::write(&duplicate, "changed").unwrap();
+        let err = replace_with_hard_link(&duplicate, &original).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read_to_string(&duplicate).unwrap(), "changed");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn moving_a_link_over_a_file_relinks_it() {
+        let scratch = tempfile::tempdir().unwrap();
+        let (source, destination) = (scratch.path().join("link"), scratch.path().join("existing"));
+        fs::write(scratch.path().join("target"), "target").unwrap();
+        fs::write(&destination, "existing").unwrap();
+        std::os::unix::fs::symlink(scratch.path().join("target"), &source).unwrap();
+
+        assert_eq!(move_replacing(&source, &destination, false).unwrap(), Transfer::Relinked);
+        assert!(!source.is_symlink());
+        assert_eq!(fs::read_link(&destination).unwrap(), scratch.path().join("target"));
+    }
+}
@@ -0,0 +1,207 @@
+//! Watch mode: keeping a directory organized as files arrive.
+//!
+//! [`watch_directory`] organizes a directory once, then waits for file system
+//! notifications (inotify on Linux) and organizes it again after each burst of
+//! changes has settled. Files that are still being written, such as partial
+//! downloads or files whose size is still changing, are left alone until they
+//! are complete.
+
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::plan::{self, SkipReason, TransferMode};
+use crate::safety;
+use crate::{apply_plan_with, CancellationToken, Config, NoProgress, RunReport};
+
+/// How often the cancellation token is checked while waiting for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Settings for watch mode.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchSettings {
+    /// How long the directory must be quiet before it is organized, in seconds.
+    /// Files modified more recently than this are considered still being written.
+    pub debounce_seconds: u64,
+    /// Extensions (without the leading dot) of files that are still being
+    /// downloaded, which are never organized.
+    pub partial_extensions: Vec<String>,
+}
+
+impl Default for WatchSettings {
+    fn default() -> Self {
+        Self {
+            debounce_seconds: 2,
+            partial_extensions: ["part", "partial", "crdownload", "download", "opdownload", "tmp"]
+                .map(str::to_owned)
+                .to_vec(),
+        }
+    }
+}
+
+/// Organizes `directory` now and again whenever files arrive, until `cancel`
+/// is cancelled.
+///
+/// Each pass plans and applies the organization exactly like
+/// [`organise_files`](crate::organise_files), except that files still being
+/// written are skipped until a later pass, and in copy mode files already
+/// copied are not copied again. Passes that find nothing to do are not
+/// journaled. `on_pass` receives the outcome of every pass that did something;
+/// a failed pass does not stop watching.
+///
+/// # Errors
+///
+/// Returns an `std::io::Error` if the directory cannot be watched, or is a
+/// protected location (see [`plan_organisation`](crate::plan_organisation)).
+pub fn watch_directory(
+    directory: &str,
+    config: &Config,
+    cancel: &CancellationToken,
+    on_pass: &mut dyn FnMut(io::Result<RunReport>),
+) -> io::Result<()> {
+    safety::check_directory(Path::new(directory), config)?;
+    safety::check_project(Path::new(directory), config)?;
+    let settings = &config.watch;
+    let debounce = Duration::from_secs(settings.debounce_seconds);
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(io::Error::other)?;
+    let mode = if config.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+    watcher.watch(Path::new(directory), mode).map_err(io::Error::other)?;
+
+    // Sizes seen in the previous pass, to spot files that are still growing.
+    let sizes = RefCell::new(HashMap::<PathBuf, u64>::new());
+    // Sources already copied, which stay in place in copy mode.
+    let mut copied = HashSet::<PathBuf>::new();
+    // Files and directories the last pass created, and until when their
+    // notifications may still be arriving.
+    let mut organized = HashSet::<PathBuf>::new();
+    let mut organized_until = Instant::now();
+
+    // Organize whatever is already there straight away.
+    let mut due = Some(Instant::now());
+    while !cancel.is_cancelled() {
+        if Instant::now() >= organized_until {
+            organized.clear();
+        }
+        let timeout = due.map_or(POLL_INTERVAL, |due| due.saturating_duration_since(Instant::now()).min(POLL_INTERVAL));
+        match receiver.recv_timeout(timeout) {
+            Ok(Ok(event)) if is_arrival(&event, &organized) => due = Some(Instant::now() + debounce),
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(err)) => on_pass(Err(io::Error::other(err))),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if due.is_none_or(|due| Instant::now() < due) {
+            continue;
+        }
+        due = None;
+
+        let hold = |path: &Path| {
+            if copied.contains(path) {
+                return Some(SkipReason::AlreadyCopied);
+            }
+            if is_partial_download(path, settings) {
+                // Finishing the download renames the file, which starts another pass.
+                return Some(SkipReason::PartialDownload);
+            }
+            is_incomplete(path, debounce, &mut sizes.borrow_mut()).then_some(SkipReason::Incomplete)
+        };
+        let plan = match plan::plan_with(directory, config, cancel, &hold) {
+            Ok(plan) => plan,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => break,
+            Err(err) => {
+                on_pass(Err(err));
+                continue;
+            }
+        };
+
+        // Look again once incomplete files have had time to finish.
+        if plan.skipped.iter().any(|item| item.reason == SkipReason::Incomplete) {
+            due = Some(Instant::now() + debounce);
+        }
+        if plan.operations.is_empty() {
+            continue;
+        }
+
+        let result = apply_plan_with(&plan, config, &mut NoProgress, cancel);
+        if let Ok(report) = &result {
+            copied.extend(
+                report
+                    .operations
+                    .iter()
+                    .filter(|operation| operation.mode == TransferMode::Copy)
+                    .map(|operation| operation.source.clone()),
+            );
+            organized.extend(report.links.iter().map(|link| link.path.clone()));
+            for operation in &report.operations {
+                organized.extend(
+                    operation
+                        .destination
+                        .ancestors()
+                        .take_while(|ancestor| *ancestor != Path::new(directory))
+                        .map(Path::to_path_buf),
+                );
+            }
+            organized_until = Instant::now() + debounce.max(POLL_INTERVAL);
+        }
+        on_pass(result);
+    }
+    Ok(())
+}
+
+/// Whether `event` may mean a new or changed file to organize. Reads, metadata
+/// changes and removals are ignored, as are changes to paths that no longer
+/// exist, such as the old names of moved files, and to the files and
+/// directories in `organized`, which the last pass created.
+fn is_arrival(event: &Event, organized: &HashSet<PathBuf>) -> bool {
+    let changed = matches!(
+        event.kind,
+        EventKind::Create(_)
+            | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Name(_) | ModifyKind::Any)
+            | EventKind::Any
+    );
+    // Events without paths, such as a request to rescan, may mean anything.
+    changed
+        && (event.paths.is_empty()
+            || event
+                .paths
+                .iter()
+                .any(|path| !organized.contains(path) && path.symlink_metadata().is_ok()))
+}
+
+/// Whether the file at `path` has one of the partial-download extensions.
+fn is_partial_download(path: &Path, settings: &WatchSettings) -> bool {
+    path.extension().is_some_and(|extension| {
+        settings
+            .partial_extensions
+            .iter()
+            .any(|partial| extension.eq_ignore_ascii_case(partial))
+    })
+}
+
+/// Whether the file at `path` looks like it is still being written: it was
+/// modified within the debounce interval, or its size changed since the
+/// previous pass.
+fn is_incomplete(path: &Path, debounce: Duration, sizes: &mut HashMap<PathBuf, u64>) -> bool {
+    let Ok(metadata) = fs::metadata(path) else {
+        return true;
+    };
+    let recently_modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < debounce);
+    let previous_size = sizes.insert(path.to_path_buf(), metadata.len());
+    let growing = previous_size.is_some_and(|size| size != metadata.len());
+
+    recently_modified || growing
+}